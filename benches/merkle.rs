@@ -0,0 +1,77 @@
+//! Benchmarks of Merkle tree construction over 100k leaves. Run with `cargo bench --bench merkle`,
+//! and again with `--features rayon` to compare with the parallel build.
+use prism::crypto::hash::H256;
+use prism::crypto::merkle::MerkleTree;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// The system allocator, keeping track of the peak number of bytes allocated.
+struct PeakAllocator;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for PeakAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let allocated = ALLOCATED.fetch_add(layout.size(), Ordering::SeqCst) + layout.size();
+        PEAK.fetch_max(allocated, Ordering::SeqCst);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.fetch_sub(layout.size(), Ordering::SeqCst);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: PeakAllocator = PeakAllocator;
+
+/// Returns the peak number of bytes allocated by `f` on top of what was already allocated.
+fn peak_memory<F: FnOnce()>(f: F) -> usize {
+    let before = ALLOCATED.load(Ordering::SeqCst);
+    PEAK.store(before, Ordering::SeqCst);
+    f();
+    PEAK.load(Ordering::SeqCst) - before
+}
+
+const LEAF_COUNT: u64 = 100_000;
+const RUNS: u32 = 20;
+
+/// Returns the average time of a run of `f`.
+fn time<F: FnMut()>(mut f: F) -> Duration {
+    f();
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        f();
+    }
+    start.elapsed() / RUNS
+}
+
+fn main() {
+    let data: Vec<H256> = (0..LEAF_COUNT).map(H256::from).collect();
+    let parallel = if cfg!(feature = "rayon") {
+        "parallel"
+    } else {
+        "sequential"
+    };
+    println!("{} leaves, {} build", LEAF_COUNT, parallel);
+    let build = time(|| {
+        MerkleTree::from_leaf_hashes(&data);
+    });
+    println!("from_leaf_hashes: {:?}", build);
+    let build = time(|| {
+        MerkleTree::new(&data);
+    });
+    println!("new: {:?}", build);
+
+    let full = peak_memory(|| {
+        MerkleTree::new(&data).root();
+    });
+    println!("new(..).root(): {} KiB peak", full / 1024);
+    let root_only = peak_memory(|| {
+        MerkleTree::root_of(&data);
+    });
+    println!("root_of: {} KiB peak", root_only / 1024);
+}
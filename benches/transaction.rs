@@ -0,0 +1,116 @@
+//! Benchmarks of transaction hashing and authorization checks. Run with
+//! `cargo bench --bench transaction`.
+use curve25519_dalek::edwards::CompressedEdwardsY;
+use ed25519_dalek::{Keypair, PublicKey, Signature};
+use prism::config::NETWORK_ID;
+use prism::crypto::hash::{Hashable, H256};
+use prism::transaction::{
+    address_from_pubkey, verify_batch, CoinId, Input, Transaction, TransactionBuilder,
+};
+use rand::rngs::OsRng;
+use std::time::{Duration, Instant};
+
+const TX_COUNT: u64 = 500;
+const RUNS: u32 = 10;
+const HASH_COUNT: usize = 1000;
+
+/// Returns the average time of a run of `f`.
+fn time<F: FnMut()>(mut f: F) -> Duration {
+    f();
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        f();
+    }
+    start.elapsed() / RUNS
+}
+
+/// Returns whether the point is torsion-free, i.e. has no small-order component.
+fn is_torsion_free(bytes: &[u8]) -> bool {
+    matches!(
+        CompressedEdwardsY::from_slice(bytes).decompress(),
+        Some(point) if point.is_torsion_free()
+    )
+}
+
+/// Returns a transaction spending a coin of each of two new key pairs, signed by both.
+fn signed_transaction(index: u64) -> Transaction {
+    let mut csprng: OsRng = OsRng::new().unwrap();
+    let keypairs = [
+        Keypair::generate(&mut csprng),
+        Keypair::generate(&mut csprng),
+    ];
+    let mut builder = TransactionBuilder::new();
+    for (coin_index, keypair) in keypairs.iter().enumerate() {
+        builder = builder.add_input(Input {
+            coin: CoinId {
+                hash: H256::from(index),
+                index: coin_index as u32,
+            },
+            value: 10,
+            owner: address_from_pubkey(&keypair.public),
+        });
+    }
+    let mut transaction = builder.add_output(20, H256::from(index)).build();
+    transaction.sign_all(&keypairs, NETWORK_ID).unwrap();
+    transaction
+}
+
+fn main() {
+    let transactions: Vec<Transaction> = (0..TX_COUNT).map(signed_transaction).collect();
+
+    println!("hashing a transaction {} times", HASH_COUNT);
+    let transaction = &transactions[0];
+    let cached = time(|| {
+        for _ in 0..HASH_COUNT {
+            std::hint::black_box(transaction.hash());
+        }
+    });
+    println!("with the cache (hash): {:?}", cached);
+    let uncached = time(|| {
+        for _ in 0..HASH_COUNT {
+            std::hint::black_box(transaction.unauthorized_hash());
+        }
+    });
+    println!("without the cache (unauthorized_hash): {:?}", uncached);
+
+    println!("{} transactions of 2 signatures", TX_COUNT);
+    let one_by_one = time(|| {
+        assert!(transactions
+            .iter()
+            .all(|x| x.verify_authorizations(NETWORK_ID)));
+    });
+    println!("verify_authorizations: {:?}", one_by_one);
+    let batch = time(|| {
+        assert!(verify_batch(&transactions, NETWORK_ID).iter().all(|x| *x));
+    });
+    println!("verify_batch: {:?}", batch);
+
+    // what batch verification would take, with and without ruling out small-order components
+    let mut messages: Vec<Vec<u8>> = vec![];
+    let mut signatures: Vec<Signature> = vec![];
+    let mut pubkeys: Vec<PublicKey> = vec![];
+    for transaction in &transactions {
+        for authorization in &transaction.authorization {
+            messages.push(transaction.signing_bytes(NETWORK_ID));
+            signatures.push(Signature::from_bytes(&authorization.signature).unwrap());
+            pubkeys.push(PublicKey::from_bytes(&authorization.pubkey).unwrap());
+        }
+    }
+    let messages: Vec<&[u8]> = messages.iter().map(|x| &x[..]).collect();
+    let dalek_batch = time(|| {
+        assert!(ed25519_dalek::verify_batch(&messages, &signatures, &pubkeys).is_ok());
+    });
+    println!("ed25519_dalek::verify_batch: {:?}", dalek_batch);
+    let checked_batch = time(|| {
+        assert!(pubkeys
+            .iter()
+            .zip(&signatures)
+            .all(|(pubkey, signature)| is_torsion_free(pubkey.as_bytes())
+                && is_torsion_free(&signature.to_bytes()[..32])));
+        assert!(ed25519_dalek::verify_batch(&messages, &signatures, &pubkeys).is_ok());
+    });
+    println!(
+        "ed25519_dalek::verify_batch with torsion checks: {:?}",
+        checked_batch
+    );
+}
@@ -1,4 +1,5 @@
 use crate::crypto::hash::{Hashable, H256};
+use bigint::uint::{U256, U512};
 // TODO: Add the address of the miner
 
 /// The header of a block.
@@ -37,10 +38,46 @@ impl Header {
             difficulty,
         }
     }
+
+    /// Search for a nonce that makes the hash of the header meet its difficulty, starting from the
+    /// current nonce and trying at most `max_tries` of them. On success, the header keeps the winning
+    /// nonce and it is returned; otherwise the header is left at the next untried nonce.
+    pub fn mine(&mut self, max_tries: u64) -> Option<u32> {
+        for _ in 0..max_tries {
+            if self.hash().meets_difficulty(&self.difficulty) {
+                return Some(self.nonce);
+            }
+            self.nonce = self.nonce.wrapping_add(1);
+        }
+        None
+    }
+}
+
+/// Retarget the difficulty: scale the target, as a big-endian 256-bit integer, by the ratio of the
+/// time the last blocks actually took to the time they were expected to take, so that faster blocks
+/// make mining harder. The result is clamped between 1, the hardest target that can still be met,
+/// and all ones, the easiest one.
+pub fn adjust_target(current: &H256, actual_time: u64, expected_time: u64) -> H256 {
+    if expected_time == 0 {
+        return (&[255u8; 32]).into();
+    }
+    let current = U512::from(U256::from_big_endian(current.as_ref()));
+    let scaled = current * U512::from(actual_time) / U512::from(expected_time);
+    let clamped = if scaled > U512::from(U256::max_value()) {
+        U256::max_value()
+    } else if scaled.is_zero() {
+        U256::one()
+    } else {
+        U256::from(scaled)
+    };
+    let mut bytes = [0u8; 32];
+    clamped.to_big_endian(&mut bytes);
+    bytes.into()
 }
 
 impl Hashable for Header {
     fn hash(&self) -> H256 {
+        // serializing plain fields into memory cannot fail
         let serialized = bincode::serialize(self).unwrap();
         let digest = ring::digest::digest(&ring::digest::SHA256, &serialized);
         digest.into()
@@ -61,6 +98,54 @@ pub mod tests {
         assert_eq!(header.hash(), header_hash_should_be);
     }
 
+    #[test]
+    fn mine() {
+        // an easy target: about one in two hashes meets it
+        let mut header = sample_header();
+        let mut difficulty = [255u8; 32];
+        difficulty[0] = 127;
+        header.difficulty = (&difficulty).into();
+        let nonce = header.mine(1000).unwrap();
+        assert_eq!(header.nonce, nonce);
+        assert!(header.hash().meets_difficulty(&header.difficulty));
+
+        // an impossible target gives up after the given number of tries
+        let mut header = sample_header();
+        header.difficulty = H256::zero();
+        let start = header.nonce;
+        assert_eq!(header.mine(10), None);
+        assert_eq!(header.nonce, start + 10);
+    }
+
+    #[test]
+    fn adjust_target() {
+        let target = H256::from(1u64 << 40);
+        // faster than expected: a harder target
+        let faster = super::adjust_target(&target, 50, 100);
+        assert_eq!(faster, H256::from(1u64 << 39));
+        assert!(faster < target);
+        // slower than expected: an easier target
+        let slower = super::adjust_target(&target, 300, 100);
+        assert_eq!(slower, H256::from(3u64 << 40));
+        assert!(slower > target);
+        assert_eq!(super::adjust_target(&target, 100, 100), target);
+
+        // clamped at all ones
+        let max: H256 = (&[255u8; 32]).into();
+        let mut easy = [255u8; 32];
+        easy[0] = 127;
+        let easy: H256 = (&easy).into();
+        assert_eq!(super::adjust_target(&easy, 3, 1), max);
+        assert_eq!(super::adjust_target(&max, u64::MAX, 1), max);
+        assert_eq!(super::adjust_target(&target, 1, 0), max);
+        // and never reaching zero
+        assert_eq!(
+            super::adjust_target(&H256::from(1u64), 1, 2),
+            H256::from(1u64)
+        );
+        assert_eq!(super::adjust_target(&target, 0, 100), H256::from(1u64));
+    }
+
     #[macro_export]
     macro_rules! gen_hashed_data {
         () => {{
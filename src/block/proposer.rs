@@ -34,11 +34,11 @@ impl PayloadSize for Content {
 
 impl Hashable for Content {
     fn hash(&self) -> H256 {
-        let tx_merkle_tree = MerkleTree::new(&self.transaction_refs);
-        let prop_merkle_tree = MerkleTree::new(&self.proposer_refs);
+        let tx_merkle_root = MerkleTree::root_of(&self.transaction_refs);
+        let prop_merkle_root = MerkleTree::root_of(&self.proposer_refs);
         let mut bytes = [0u8; 64];
-        bytes[..32].copy_from_slice(tx_merkle_tree.root().as_ref());
-        bytes[32..64].copy_from_slice(prop_merkle_tree.root().as_ref());
+        bytes[..32].copy_from_slice(tx_merkle_root.as_ref());
+        bytes[32..64].copy_from_slice(prop_merkle_root.as_ref());
         ring::digest::digest(&ring::digest::SHA256, &bytes).into()
     }
 }
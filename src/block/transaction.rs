@@ -1,7 +1,8 @@
 use crate::crypto::hash::{Hashable, H256};
-use crate::crypto::merkle::MerkleTree;
+use crate::crypto::merkle::{verify_by_hash, MerkleProof, MerkleTree};
 use crate::experiment::performance_counter::PayloadSize;
 use crate::transaction::Transaction;
+use std::convert::TryFrom;
 
 /// The content of a transaction block.
 #[derive(Serialize, Deserialize, Debug, Default, Clone)]
@@ -17,22 +18,251 @@ impl Content {
 }
 
 impl PayloadSize for Content {
+    /// Return the total size of the transactions. It saturates at `usize::MAX` rather than wrapping
+    /// around.
     fn size(&self) -> usize {
-        let mut total = 0;
-        for t in &self.transactions {
-            total += t.size();
-        }
-        total
+        self.transactions
+            .iter()
+            .fold(0usize, |total, t| total.saturating_add(t.size()))
     }
 }
 
 impl Hashable for Content {
     fn hash(&self) -> H256 {
         // TODO: we are hashing txs in a merkle tree.
-        let merkle_tree = MerkleTree::new(&self.transactions);
-        merkle_tree.root()
+        transactions_root(&self.transactions)
     }
 }
 
+/// The transactions root of a block with no transactions, which is all zeros.
+pub const EMPTY_TXS_ROOT: H256 = H256::zero();
+
+/// Returns the Merkle root of a list of transactions, which commits to them in the content hash of
+/// a transaction block. The root of an empty list is `EMPTY_TXS_ROOT`.
+///
+/// The leaves are the `wtxid` of the transactions, not their IDs (`Transaction::hash`). The content
+/// hash is the only commitment of a transaction block, and there is no separate witness
+/// commitment as in a SegWit coinbase, so a root over IDs would leave the authorizations
+/// uncommitted: anyone relaying a block could replace the signatures of its transactions without
+/// changing its hash. In exchange, inclusion proofs are over `wtxid`s and must be rebuilt when a
+/// transaction is signed again, while coins are still named after the ID, which the
+/// authorizations cannot change.
+pub fn transactions_root(transactions: &[Transaction]) -> H256 {
+    tree_over_wtxids(transactions).1
+}
+
+/// Returns the hashes the Merkle tree of a transaction block is built over, i.e. the `wtxid` of
+/// each transaction, together with the root of the tree. A server can keep the hashes to build
+/// proofs later without hashing the transactions again. There is no tree over transaction IDs
+/// (`Transaction::hash`), since blocks do not commit to one (see `transactions_root`).
+pub fn tree_over_wtxids(transactions: &[Transaction]) -> (Vec<H256>, H256) {
+    let wtxids: Vec<H256> = transactions.iter().map(|x| x.wtxid()).collect();
+    if wtxids.is_empty() {
+        return (wtxids, EMPTY_TXS_ROOT);
+    }
+    let root = MerkleTree::from_leaf_hashes(&wtxids).root();
+    (wtxids, root)
+}
+
+/// Returns the transactions root of a list of transactions together with their number, which a
+/// light client needs to check the shape of the tree an inclusion proof is claimed to be in. Panics
+/// if there are more than `u32::MAX` transactions.
+pub fn txs_commitment(transactions: &[Transaction]) -> (H256, u32) {
+    let count = u32::try_from(transactions.len()).expect("too many transactions to commit to");
+    (transactions_root(transactions), count)
+}
+
+/// Verify that a transaction is in a transaction block with the given transactions root and number
+/// of transactions (see `txs_commitment`), e.g. for a light client to confirm a payment. The proof
+/// must be as for `verify_inclusion_tx`, and must be for a tree of `tx_count` leaves, so that the
+/// server cannot lie about the shape of the tree.
+pub fn verify_inclusion(
+    transaction: &Transaction,
+    proof: &MerkleProof,
+    block_txs_root: &H256,
+    tx_count: u32,
+) -> bool {
+    proof.leaf_count as u64 == u64::from(tx_count)
+        && verify_inclusion_tx(transaction, proof, block_txs_root)
+}
+
+/// Verify that a transaction is in a transaction block with the given transactions root, hashing
+/// the transaction itself so that the caller cannot pass the wrong leaf. The proof must be of the
+/// `wtxid` of the transaction in the tree built by `tree_over_wtxids`, since that is what blocks
+/// commit to (see `transactions_root`), so a proof no longer holds once the transaction is signed
+/// again. Prefer `verify_inclusion` when the number of transactions is known.
+pub fn verify_inclusion_tx(transaction: &Transaction, proof: &MerkleProof, root: &H256) -> bool {
+    verify_by_hash(root, &transaction.wtxid(), proof)
+}
+
+/// Sort transactions by ID (and by `wtxid` among equal IDs), so that miners with the same set of
+/// transactions build the same block content and Merkle root. A coinbase transaction, if first,
+/// stays first. The order ignores dependencies, so a transaction may end up before the one that
+/// creates a coin it spends.
+pub fn canonical_sort(transactions: &mut [Transaction]) {
+    let start = match transactions.first() {
+        Some(first) if first.is_coinbase() => 1,
+        _ => 0,
+    };
+    transactions[start..].sort_by_cached_key(|x| (x.hash(), x.wtxid()));
+}
+
 #[cfg(test)]
-pub mod tests {}
+pub mod tests {
+    use super::*;
+    use crate::transaction::tests::{generate_keypair, generate_signed_transaction};
+    use crate::transaction::Output;
+
+    fn transaction(value: u64) -> Transaction {
+        Transaction::new(
+            vec![],
+            vec![Output {
+                value,
+                recipient: (&[1; 32]).into(),
+            }],
+        )
+    }
+
+    #[test]
+    fn empty_txs_root() {
+        assert_eq!(super::transactions_root(&[]), EMPTY_TXS_ROOT);
+        assert_eq!(Content::new(vec![]).hash(), EMPTY_TXS_ROOT);
+        assert_eq!(super::txs_commitment(&[]), (EMPTY_TXS_ROOT, 0));
+        let transaction = generate_signed_transaction(&[generate_keypair()]);
+        assert_ne!(super::transactions_root(&[transaction]), EMPTY_TXS_ROOT);
+    }
+
+    #[test]
+    fn verify_inclusion() {
+        let transactions: Vec<Transaction> = (0..5)
+            .map(|_| generate_signed_transaction(&[generate_keypair()]))
+            .collect();
+        let (wtxids, root) = tree_over_wtxids(&transactions);
+        let tree = MerkleTree::from_leaf_hashes(&wtxids);
+        let proof = tree.get_proof_from_index(3).unwrap();
+        assert!(super::verify_inclusion(&transactions[3], &proof, &root, 5));
+        assert!(!super::verify_inclusion(&transactions[2], &proof, &root, 5));
+        let (other_root, other_count) = super::txs_commitment(&transactions[..4]);
+        assert!(!super::verify_inclusion(
+            &transactions[3],
+            &proof,
+            &other_root,
+            other_count
+        ));
+        // the proof also covers the authorizations
+        let mut resigned = transactions[3].clone();
+        resigned.authorization[0].signature[0] ^= 1;
+        assert!(!super::verify_inclusion(&resigned, &proof, &root, 5));
+    }
+
+    #[test]
+    fn txs_commitment() {
+        let transactions: Vec<Transaction> = (0..5)
+            .map(|_| generate_signed_transaction(&[generate_keypair()]))
+            .collect();
+        let (root, count) = super::txs_commitment(&transactions);
+        assert_eq!(root, super::transactions_root(&transactions));
+        assert_eq!(count, 5);
+        assert_eq!(super::txs_commitment(&[]), (H256::zero(), 0));
+
+        let (wtxids, _) = tree_over_wtxids(&transactions);
+        let proof = MerkleTree::from_leaf_hashes(&wtxids)
+            .get_proof_from_index(4)
+            .unwrap();
+        assert!(super::verify_inclusion(
+            &transactions[4],
+            &proof,
+            &root,
+            count
+        ));
+        // the claimed count must match the number of leaves of the proof
+        assert!(!super::verify_inclusion(&transactions[4], &proof, &root, 4));
+        assert!(!super::verify_inclusion(&transactions[4], &proof, &root, 6));
+        let mut reshaped = proof.clone();
+        reshaped.leaf_count = 6;
+        assert!(!super::verify_inclusion(
+            &transactions[4],
+            &reshaped,
+            &root,
+            count
+        ));
+    }
+
+    #[test]
+    fn verify_inclusion_tx() {
+        let transactions: Vec<Transaction> = (0..5)
+            .map(|_| generate_signed_transaction(&[generate_keypair()]))
+            .collect();
+        let (wtxids, root) = tree_over_wtxids(&transactions);
+        assert_eq!(root, super::transactions_root(&transactions));
+        let proof = MerkleTree::from_leaf_hashes(&wtxids)
+            .get_proof_from_index(3)
+            .unwrap();
+        assert!(super::verify_inclusion_tx(&transactions[3], &proof, &root));
+        assert!(!super::verify_inclusion_tx(&transactions[2], &proof, &root));
+        // the block commits to the authorizations, so a re-signed transaction is not included
+        let mut resigned = transactions[3].clone();
+        resigned.authorization[0].signature[0] ^= 1;
+        assert!(!super::verify_inclusion_tx(&resigned, &proof, &root));
+    }
+
+    #[test]
+    fn canonical_sort() {
+        let mut transactions: Vec<Transaction> = (0..6)
+            .map(|_| generate_signed_transaction(&[generate_keypair()]))
+            .collect();
+        let mut reversed: Vec<Transaction> = transactions.iter().rev().cloned().collect();
+        super::canonical_sort(&mut transactions);
+        super::canonical_sort(&mut reversed);
+        assert_eq!(transactions, reversed);
+        assert!(transactions.windows(2).all(|w| w[0].hash() <= w[1].hash()));
+        assert_eq!(
+            super::transactions_root(&transactions),
+            super::transactions_root(&reversed)
+        );
+
+        // the coinbase transaction stays first
+        let coinbase = Transaction::coinbase((&[255; 32]).into(), 50, H256::from(1));
+        let mut with_coinbase = vec![coinbase.clone()];
+        with_coinbase.extend(reversed.iter().rev().cloned());
+        super::canonical_sort(&mut with_coinbase);
+        assert_eq!(with_coinbase[0], coinbase);
+        assert_eq!(with_coinbase[1..], transactions[..]);
+        super::canonical_sort(&mut []);
+    }
+
+    #[test]
+    fn transactions_root() {
+        assert!(super::transactions_root(&[]).is_zero());
+        let transactions = vec![transaction(1), transaction(2), transaction(3)];
+        let root = super::transactions_root(&transactions);
+        assert!(!root.is_zero());
+        let wtxids: Vec<H256> = transactions.iter().map(|x| x.wtxid()).collect();
+        assert_eq!(root, MerkleTree::from_leaf_hashes(&wtxids).root());
+        let content = Content::new(transactions.clone());
+        assert_eq!(content.hash(), root);
+
+        let reordered = vec![transaction(2), transaction(1), transaction(3)];
+        assert_ne!(super::transactions_root(&reordered), root);
+
+        let (wtxids, tree_root) = tree_over_wtxids(&transactions);
+        assert_eq!(tree_root, root);
+        assert_eq!(wtxids.len(), 3);
+        assert_eq!(wtxids[1], transactions[1].wtxid());
+        let proof = MerkleTree::from_leaf_hashes(&wtxids)
+            .get_proof_from_index(1)
+            .unwrap();
+        assert!(proof.verify(&root, &wtxids[1]));
+        assert_eq!(tree_over_wtxids(&[]), (vec![], H256::zero()));
+
+        // replacing an authorization changes the root, though not the transaction ID
+        let signed = generate_signed_transaction(&[generate_keypair()]);
+        let mut resigned = signed.clone();
+        resigned.authorization[0].signature[0] ^= 1;
+        assert_eq!(resigned.hash(), signed.hash());
+        assert_ne!(
+            super::transactions_root(&[resigned]),
+            super::transactions_root(&[signed])
+        );
+    }
+}
@@ -6,6 +6,10 @@ const PROPOSER_TX_REF_HEADROOM: f32 = 10.0;
 const SORTITION_PRECISION: u64 = std::u64::MAX;
 const DECONFIRM_HEADROOM: f32 = 1.05;
 
+/// Identifier of the network (e.g. a testnet), signed with every transaction so that a transaction
+/// cannot be replayed on another network.
+pub const NETWORK_ID: u32 = 0;
+
 // Chain IDs
 pub const PROPOSER_INDEX: u16 = 0;
 pub const TRANSACTION_INDEX: u16 = 1;
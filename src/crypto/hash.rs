@@ -7,9 +7,100 @@ pub trait Hashable {
 }
 
 /// A SHA256 hash.
-#[derive(Eq, PartialEq, Serialize, Deserialize, Clone, Hash, Default, Copy)]
+#[derive(Eq, PartialEq, Clone, Hash, Default, Copy)]
 pub struct H256([u8; 32]); // big endian u256
 
+impl H256 {
+    /// Returns the all-zero hash, used e.g. as the root of an empty Merkle tree.
+    pub const fn zero() -> H256 {
+        H256([0; 32])
+    }
+
+    /// Returns whether all bits of this hash are zero.
+    pub fn is_zero(&self) -> bool {
+        self.0.iter().all(|x| *x == 0)
+    }
+
+    /// Compare two hashes in constant time, i.e. without stopping at the first differing byte, so
+    /// that the comparison does not leak how much of a secret-derived hash was guessed right.
+    pub fn ct_eq(&self, other: &H256) -> bool {
+        self.0
+            .iter()
+            .zip(other.0.iter())
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+            == 0
+    }
+
+    /// Returns whether this hash, as a big-endian 256-bit integer, is a valid proof of work for
+    /// the given target, i.e. strictly smaller than it, as checked by the miner and in block
+    /// validation. A hash equal to the target does not qualify: block validation
+    /// (`BlockchainConfig::sortition_hash`) requires `hash < difficulty`, so accepting it here
+    /// would let the miner produce blocks that other nodes reject.
+    pub fn meets_difficulty(&self, target: &H256) -> bool {
+        self < target
+    }
+
+    /// Returns the XOR distance between two hashes, e.g. for DHT-style routing.
+    pub fn xor_distance(&self, other: &H256) -> H256 {
+        let mut distance = [0u8; 32];
+        for (d, (a, b)) in distance.iter_mut().zip(self.0.iter().zip(other.0.iter())) {
+            *d = a ^ b;
+        }
+        H256(distance)
+    }
+
+    /// Returns the position of the most significant bit in which two hashes differ, starting from
+    /// 0, or 256 if they are equal.
+    pub fn bucket_index(&self, other: &H256) -> u32 {
+        self.xor_distance(other).leading_zeros()
+    }
+
+    /// Returns the number of leading zero bits of this hash, as a big-endian 256-bit integer.
+    pub fn leading_zeros(&self) -> u32 {
+        let higher = u128::from_be_bytes(self.0[0..16].try_into().unwrap());
+        let lower = u128::from_be_bytes(self.0[16..32].try_into().unwrap());
+        if higher == 0 {
+            128 + lower.leading_zeros()
+        } else {
+            higher.leading_zeros()
+        }
+    }
+
+    /// Returns the 256-bit integer `input` as a hash in big-endian, i.e. in the last 8 bytes, the
+    /// byte order in which hashes are compared (see `Ord`) and difficulty targets are written.
+    pub fn from_u64_be(input: u64) -> H256 {
+        let mut buffer: [u8; 32] = [0; 32];
+        buffer[24..32].copy_from_slice(&input.to_be_bytes());
+        H256(buffer)
+    }
+
+    /// Returns the 256-bit integer `input` as a hash in little-endian, i.e. in the first 8 bytes.
+    /// This is the byte reversal of `from_u64_be`, and as a hash it is not ordered as `input` is.
+    pub fn from_u64_le(input: u64) -> H256 {
+        let mut buffer: [u8; 32] = [0; 32];
+        buffer[0..8].copy_from_slice(&input.to_le_bytes());
+        H256(buffer)
+    }
+
+    /// Returns the 8 most significant bytes of this hash as a big-endian integer.
+    pub fn to_u64_prefix_be(&self) -> u64 {
+        u64::from_be_bytes(self.0[0..8].try_into().unwrap())
+    }
+
+    /// Returns the `k` most significant bits of this hash as an integer, e.g. to assign coins to
+    /// one of 2^k shards. Panics if `k` is greater than 64.
+    pub fn prefix_bits(&self, k: u32) -> u64 {
+        assert!(k <= 64, "prefix of more than 64 bits");
+        self.to_u64_prefix_be().checked_shr(64 - k).unwrap_or(0)
+    }
+
+    /// Returns the byte at the given index, where byte 0 is the most significant one, or `None` if
+    /// the index is out of range.
+    pub fn nth_byte(&self, i: usize) -> Option<u8> {
+        self.0.get(i).cloned()
+    }
+}
+
 impl std::fmt::Display for H256 {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         let start = if let Some(precision) = f.precision() {
@@ -28,6 +119,41 @@ impl std::fmt::Display for H256 {
     }
 }
 
+impl std::str::FromStr for H256 {
+    type Err = hex::FromHexError;
+
+    /// Parse a hash from 64 hex digits, optionally prefixed with `0x`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let digits = s.strip_prefix("0x").unwrap_or(s);
+        let mut bytes = [0u8; 32];
+        hex::decode_to_slice(digits, &mut bytes)?;
+        Ok(H256(bytes))
+    }
+}
+
+/// A hash is serialized as 64 hex digits in human-readable formats such as JSON, and as 32 raw
+/// bytes otherwise (e.g. bincode).
+impl serde::Serialize for H256 {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&hex::encode(self.0))
+        } else {
+            serializer.serialize_newtype_struct("H256", &self.0)
+        }
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for H256 {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            let s = <String as serde::Deserialize>::deserialize(deserializer)?;
+            s.parse().map_err(serde::de::Error::custom)
+        } else {
+            <[u8; 32] as serde::Deserialize>::deserialize(deserializer).map(H256)
+        }
+    }
+}
+
 impl std::fmt::Debug for H256 {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(
@@ -44,6 +170,53 @@ impl Hashable for H256 {
     }
 }
 
+/// A reference hashes as the value it refers to, so that e.g. a Merkle tree can be built over a
+/// slice of references without cloning the values.
+impl<T: Hashable + ?Sized> Hashable for &T {
+    fn hash(&self) -> H256 {
+        (**self).hash()
+    }
+}
+
+/// A list hashes as the SHA256 of its length, as a little-endian `u64`, followed by the hashes of
+/// its elements. The length prefix keeps lists of different lengths from being confused, e.g. a
+/// list of one element with the element itself.
+impl<T: Hashable> Hashable for [T] {
+    fn hash(&self) -> H256 {
+        hash_list(self.iter().map(|x| x.hash()), self.len())
+    }
+}
+
+impl<T: Hashable> Hashable for Vec<T> {
+    fn hash(&self) -> H256 {
+        self.as_slice().hash()
+    }
+}
+
+/// A pair hashes as the list of its two elements' hashes.
+impl<A: Hashable, B: Hashable> Hashable for (A, B) {
+    fn hash(&self) -> H256 {
+        hash_list(vec![self.0.hash(), self.1.hash()], 2)
+    }
+}
+
+/// A triple hashes as the list of its three elements' hashes.
+impl<A: Hashable, B: Hashable, C: Hashable> Hashable for (A, B, C) {
+    fn hash(&self) -> H256 {
+        hash_list(vec![self.0.hash(), self.1.hash(), self.2.hash()], 3)
+    }
+}
+
+/// Returns the SHA256 of the given number of hashes, prefixed with that number.
+fn hash_list<I: IntoIterator<Item = H256>>(hashes: I, len: usize) -> H256 {
+    let mut ctx = ring::digest::Context::new(&ring::digest::SHA256);
+    ctx.update(&(len as u64).to_le_bytes());
+    for hash in hashes {
+        ctx.update(&hash.0);
+    }
+    ctx.finish().into()
+}
+
 impl std::convert::AsRef<[u8]> for H256 {
     fn as_ref(&self) -> &[u8] {
         &self.0
@@ -78,6 +251,23 @@ impl std::convert::From<H256> for [u8; 32] {
     }
 }
 
+/// A small integer as a hash, e.g. a test hash or an easy difficulty target. It is big-endian and
+/// padded with zeros in the high bytes.
+impl std::convert::From<u64> for H256 {
+    fn from(input: u64) -> H256 {
+        H256::from_u64_be(input)
+    }
+}
+
+/// A slice of 32 bytes as a hash. Fails on any other length.
+impl std::convert::TryFrom<&[u8]> for H256 {
+    type Error = std::array::TryFromSliceError;
+
+    fn try_from(input: &[u8]) -> Result<H256, Self::Error> {
+        input.try_into().map(H256)
+    }
+}
+
 impl std::convert::From<ring::digest::Digest> for H256 {
     fn from(input: ring::digest::Digest) -> H256 {
         let mut raw_hash: [u8; 32] = [0; 32];
@@ -86,6 +276,8 @@ impl std::convert::From<ring::digest::Digest> for H256 {
     }
 }
 
+/// Hashes are ordered as big-endian 256-bit integers, i.e. byte 0 is the most significant one.
+/// `meets_difficulty` relies on this order.
 impl Ord for H256 {
     fn cmp(&self, other: &H256) -> std::cmp::Ordering {
         let self_higher = u128::from_be_bytes(self.0[0..16].try_into().unwrap());
@@ -143,6 +335,206 @@ pub mod tests {
         assert_eq!(some_hash == same_hash, true);
     }
 
+    #[test]
+    fn ct_eq() {
+        for _ in 0..100 {
+            let a = generate_random_hash();
+            let b = generate_random_hash();
+            assert_eq!(a.ct_eq(&b), a == b);
+            assert!(a.ct_eq(&a));
+            let copy: H256 = (&<[u8; 32]>::from(&a)).into();
+            assert!(a.ct_eq(&copy));
+        }
+        // hashes differing in a single bit of the last byte
+        let mut bytes = [0u8; 32];
+        let zero: H256 = (&bytes).into();
+        bytes[31] = 1;
+        let one: H256 = (&bytes).into();
+        assert!(!zero.ct_eq(&one));
+        assert_eq!(zero.ct_eq(&one), zero == one);
+    }
+
+    #[test]
+    fn serde() {
+        let hash = generate_random_hash();
+        let json = serde_json::to_string(&hash).unwrap();
+        assert_eq!(json, format!("\"{}\"", hex::encode(hash.0)));
+        assert_eq!(serde_json::from_str::<H256>(&json).unwrap(), hash);
+        assert!(serde_json::from_str::<H256>("\"0102\"").is_err());
+        // the binary encoding is the raw bytes
+        let bytes = bincode::serialize(&hash).unwrap();
+        assert_eq!(bytes, hash.0.to_vec());
+        assert_eq!(bincode::deserialize::<H256>(&bytes).unwrap(), hash);
+    }
+
+    #[test]
+    fn composite() {
+        let a = generate_random_hash();
+        let b = generate_random_hash();
+        let c = generate_random_hash();
+        assert_eq!(vec![a, b].hash(), vec![a, b].hash());
+        assert_ne!(vec![a, b].hash(), vec![b, a].hash());
+        assert_eq!(vec![a, b].hash(), [a, b][..].hash());
+        // the length prefix tells lists apart
+        assert_ne!(vec![a].hash(), a.hash());
+        assert_ne!(
+            vec![vec![a, b], vec![c]].hash(),
+            vec![vec![a], vec![b, c]].hash()
+        );
+        assert_ne!(Vec::<H256>::new().hash(), vec![H256::zero()].hash());
+        // tuples, possibly of different types
+        assert_eq!((a, b).hash(), vec![a, b].hash());
+        assert_ne!((a, b).hash(), (b, a).hash());
+        assert_eq!((a, vec![b], c).hash(), (a, vec![b], c).hash());
+        assert_ne!((a, vec![b], c).hash(), (a, b, c).hash());
+    }
+
+    #[test]
+    fn meets_difficulty() {
+        let zeros: H256 = (&[0u8; 32]).into();
+        let ones: H256 = (&[255u8; 32]).into();
+        assert!(zeros < ones);
+        assert!(zeros.meets_difficulty(&ones));
+        assert!(!ones.meets_difficulty(&zeros));
+        // the target itself is not a valid proof of work
+        assert!(!ones.meets_difficulty(&ones));
+        // the first byte is the most significant one
+        let small: H256 =
+            (&hex!("00ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff")).into();
+        let target: H256 =
+            (&hex!("0100000000000000000000000000000000000000000000000000000000000000")).into();
+        assert!(small.meets_difficulty(&target));
+        assert!(!target.meets_difficulty(&small));
+    }
+
+    #[test]
+    fn leading_zeros() {
+        let zeros: H256 = (&[0u8; 32]).into();
+        assert_eq!(zeros.leading_zeros(), 256);
+        let ones: H256 = (&[255u8; 32]).into();
+        assert_eq!(ones.leading_zeros(), 0);
+        let hash: H256 =
+            (&hex!("000f000000000000000000000000000000000000000000000000000000000000")).into();
+        assert_eq!(hash.leading_zeros(), 12);
+        let hash: H256 =
+            (&hex!("0000000000000000000000000000000000000000000000000000000000000001")).into();
+        assert_eq!(hash.leading_zeros(), 255);
+        let hash: H256 =
+            (&hex!("0000000000000000000000000000000080000000000000000000000000000000")).into();
+        assert_eq!(hash.leading_zeros(), 128);
+    }
+
+    #[test]
+    fn conversions() {
+        use std::convert::TryFrom;
+        let one = H256::from(1u64);
+        let mut expected = [0u8; 32];
+        expected[31] = 1;
+        assert_eq!(one, H256::from(expected));
+        assert!(H256::from(255u64) < H256::from(256u64));
+        assert_eq!(H256::from(u64::MAX).as_ref()[24..], [255u8; 8]);
+        let hash = generate_random_hash();
+        assert_eq!(H256::try_from(hash.as_ref()).unwrap(), hash);
+        assert!(H256::try_from(&hash.as_ref()[..31]).is_err());
+        assert!(H256::try_from(&[0u8; 33][..]).is_err());
+    }
+
+    #[test]
+    fn endianness() {
+        let value = 0x0102_0304_0506_0708u64;
+        let be = H256::from_u64_be(value);
+        let le = H256::from_u64_le(value);
+        assert_eq!(be, H256::from(value));
+        let mut reversed: [u8; 32] = be.into();
+        reversed.reverse();
+        assert_eq!(le, H256::from(reversed));
+        assert_eq!(be.to_u64_prefix_be(), 0);
+        assert_eq!(le.to_u64_prefix_be(), 0x0807_0605_0403_0201);
+        assert_eq!(
+            H256::from_u64_le(value.swap_bytes()).to_u64_prefix_be(),
+            value
+        );
+
+        // big-endian hashes are ordered, and meet difficulty, as their integers
+        assert!(H256::from_u64_be(255).meets_difficulty(&H256::from_u64_be(256)));
+        assert!(!H256::from_u64_be(256).meets_difficulty(&H256::from_u64_be(255)));
+        // little-endian ones are not
+        assert!(!H256::from_u64_le(255).meets_difficulty(&H256::from_u64_le(256)));
+    }
+
+    #[test]
+    fn prefix_bits() {
+        let hash: H256 =
+            (&hex!("a5f0000000000000ff00000000000000000000000000000000000000000000ff")).into();
+        assert_eq!(hash.prefix_bits(0), 0);
+        assert_eq!(hash.prefix_bits(1), 1);
+        assert_eq!(hash.prefix_bits(4), 0xa);
+        assert_eq!(hash.prefix_bits(12), 0xa5f);
+        assert_eq!(hash.prefix_bits(64), 0xa5f0_0000_0000_0000);
+        assert_eq!(hash.nth_byte(0), Some(0xa5));
+        assert_eq!(hash.nth_byte(8), Some(0xff));
+        assert_eq!(hash.nth_byte(31), Some(0xff));
+        assert_eq!(hash.nth_byte(32), None);
+        assert_eq!(hash.nth_byte(usize::MAX), None);
+    }
+
+    #[test]
+    fn from_str() {
+        use std::str::FromStr;
+        for _ in 0..10 {
+            let hash = generate_random_hash();
+            let string = hash.to_string();
+            assert_eq!(string.len(), 64);
+            assert_eq!(H256::from_str(&string).unwrap(), hash);
+            assert_eq!(H256::from_str(&format!("0x{}", string)).unwrap(), hash);
+        }
+        let hash: H256 =
+            (&hex!("0a0b0c0d0e0f0e0d0a0b0c0d0e0f0e0d0a0b0c0d0e0f0e0d0a0b0c0d0e0f0e0d")).into();
+        assert_eq!(
+            "0A0B0C0D0E0F0E0D0A0B0C0D0E0F0E0D0A0B0C0D0E0F0E0D0A0B0C0D0E0F0E0D"
+                .parse::<H256>()
+                .unwrap(),
+            hash
+        );
+        // wrong length
+        assert!(H256::from_str("0a0b").is_err());
+        assert!(H256::from_str(&format!("{}00", hash)).is_err());
+        // invalid digit
+        assert!(H256::from_str(&format!("{}", hash).replace("a", "g")).is_err());
+    }
+
+    #[test]
+    fn xor_distance() {
+        let hash = generate_random_hash();
+        assert_eq!(hash.xor_distance(&hash), (&[0u8; 32]).into());
+        assert_eq!(hash.bucket_index(&hash), 256);
+        let other = generate_random_hash();
+        assert_eq!(hash.xor_distance(&other), other.xor_distance(&hash));
+
+        let mut bytes: [u8; 32] = hash.into();
+        bytes[0] ^= 0x80;
+        let top_bit_flipped: H256 = bytes.into();
+        assert_eq!(hash.bucket_index(&top_bit_flipped), 0);
+        let mut expected = [0u8; 32];
+        expected[0] = 0x80;
+        assert_eq!(hash.xor_distance(&top_bit_flipped), expected.into());
+
+        let mut bytes: [u8; 32] = hash.into();
+        bytes[1] ^= 0x10;
+        assert_eq!(hash.bucket_index(&bytes.into()), 11);
+    }
+
+    #[test]
+    fn zero() {
+        assert!(H256::zero().is_zero());
+        assert_eq!(H256::zero(), (&[0u8; 32]).into());
+        assert_eq!(H256::zero(), H256::default());
+        assert!(!generate_random_hash().is_zero());
+        let mut bytes = [0u8; 32];
+        bytes[31] = 1;
+        assert!(!H256::from(bytes).is_zero());
+    }
+
     #[test]
     fn convert_u8() {
         let source = hex!("0101010102020202010101010202020201010101020202020101010102020202");
@@ -0,0 +1,203 @@
+use super::hash::{Hashable, H256};
+use super::merkle::{MerkleError, MerkleHasher, Sha256Hasher};
+use std::marker::PhantomData;
+
+/// A k-ary Merkle tree using SHA256.
+pub type KaryMerkleTree = GenericKaryMerkleTree<Sha256Hasher>;
+
+/// A Merkle tree over hasher `H` in which each internal node has `arity` children, so that proofs
+/// have fewer layers than in a binary tree (though `arity - 1` siblings per layer).
+///
+/// Leaves and internal nodes are hashed with `H::hash_leaf` and `H::hash_children`. A layer other
+/// than the root whose size is not a multiple of `arity` is padded by repeating its last node, so
+/// with an arity of 2 the tree is the same as `GenericMerkleTree`.
+#[derive(Debug, Clone)]
+pub struct GenericKaryMerkleTree<H> {
+    arity: usize,
+    /// The layers from the leaves up to the root, including the padding.
+    layers: Vec<Vec<H256>>,
+    leaf_count: usize,
+    hasher: PhantomData<H>,
+}
+
+impl<H: MerkleHasher> GenericKaryMerkleTree<H> {
+    /// Build a tree with the given arity over the data. Panics if the arity is smaller than 2.
+    pub fn new<T>(data: &[T], arity: usize) -> Self
+    where
+        T: Hashable,
+    {
+        assert!(arity >= 2, "the arity of a Merkle tree must be at least 2");
+        let mut layers = vec![];
+        let mut layer: Vec<H256> = data.iter().map(|x| H::hash_leaf(&x.hash())).collect();
+        while layer.len() > 1 {
+            let padded_size = ((layer.len() - 1) / arity + 1) * arity;
+            let last = layer[layer.len() - 1];
+            layer.resize(padded_size, last);
+            let parents = layer.chunks(arity).map(H::hash_children).collect();
+            layers.push(layer);
+            layer = parents;
+        }
+        layers.push(layer);
+        Self {
+            arity,
+            layers,
+            leaf_count: data.len(),
+            hasher: PhantomData,
+        }
+    }
+
+    /// Returns the root of the tree, or all zeros if the tree is empty.
+    pub fn root(&self) -> H256 {
+        match self.layers[self.layers.len() - 1].first() {
+            Some(root) => *root,
+            None => H256::zero(),
+        }
+    }
+
+    /// Returns the number of children of each internal node.
+    pub fn arity(&self) -> usize {
+        self.arity
+    }
+
+    /// Returns the Merkle proof of the data at the given index.
+    pub fn proof(&self, index: usize) -> Result<KaryMerkleProof, MerkleError> {
+        if self.leaf_count == 0 {
+            return Err(MerkleError::EmptyTree);
+        }
+        if index >= self.leaf_count {
+            return Err(MerkleError::OutOfBounds(index));
+        }
+        let mut siblings = vec![];
+        let mut position = index;
+        for layer in &self.layers[..self.layers.len() - 1] {
+            let start = position - position % self.arity;
+            let group = &layer[start..start + self.arity];
+            siblings.push(
+                group
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| start + i != position)
+                    .map(|(_, node)| *node)
+                    .collect(),
+            );
+            position /= self.arity;
+        }
+        Ok(KaryMerkleProof {
+            siblings,
+            index,
+            leaf_count: self.leaf_count,
+            arity: self.arity,
+        })
+    }
+}
+
+/// A proof that a leaf is in a k-ary Merkle tree.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct KaryMerkleProof {
+    /// For each layer from the leaves up to the root, the `arity - 1` siblings of the node on the
+    /// path, from left to right.
+    pub siblings: Vec<Vec<H256>>,
+    /// Index of the leaf.
+    pub index: usize,
+    /// Number of leaves in the tree.
+    pub leaf_count: usize,
+    /// Number of children of each internal node.
+    pub arity: usize,
+}
+
+impl KaryMerkleProof {
+    /// Verify that the data hash with this proof will produce the Merkle root of a k-ary tree using
+    /// SHA256.
+    pub fn verify(&self, root: &H256, data: &H256) -> bool {
+        self.verify_with_hasher::<Sha256Hasher>(root, data)
+    }
+
+    /// Same as `verify`, but for a tree built with hasher `H`.
+    pub fn verify_with_hasher<H: MerkleHasher>(&self, root: &H256, data: &H256) -> bool {
+        if self.arity < 2 || self.index >= self.leaf_count {
+            return false;
+        }
+        // the proof must have one layer of siblings per layer below the root
+        let mut depth = 0;
+        let mut layer_size = self.leaf_count;
+        while layer_size > 1 {
+            layer_size = (layer_size - 1) / self.arity + 1;
+            depth += 1;
+        }
+        if self.siblings.len() != depth {
+            return false;
+        }
+        let mut acc = H::hash_leaf(data);
+        let mut position = self.index;
+        for siblings in &self.siblings {
+            if siblings.len() != self.arity - 1 {
+                return false;
+            }
+            let mut children = siblings.clone();
+            children.insert(position % self.arity, acc);
+            acc = H::hash_children(&children);
+            position /= self.arity;
+        }
+        acc.ct_eq(root)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::hash::tests::generate_random_hash;
+    use crate::crypto::merkle::MerkleTree;
+
+    #[test]
+    fn binary() {
+        // an arity of 2 gives the same tree as the binary Merkle tree
+        let mut input_data: Vec<H256> = vec![];
+        for _ in 0..=9 {
+            let tree = KaryMerkleTree::new(&input_data, 2);
+            assert_eq!(tree.root(), MerkleTree::new(&input_data).root());
+            input_data.push(generate_random_hash());
+        }
+    }
+
+    #[test]
+    fn quaternary() {
+        let input_data: Vec<H256> = (0..16).map(|_| generate_random_hash()).collect();
+        let tree = KaryMerkleTree::new(&input_data, 4);
+        assert_eq!(tree.arity(), 4);
+        let root = tree.root();
+        for (idx, datum) in input_data.iter().enumerate() {
+            let proof = tree.proof(idx).unwrap();
+            assert_eq!(proof.siblings.len(), 2);
+            assert!(proof.siblings.iter().all(|x| x.len() == 3));
+            assert!(proof.verify(&root, &datum.hash()));
+            let other = &input_data[(idx + 1) % input_data.len()];
+            assert!(!proof.verify(&root, &other.hash()));
+
+            let mut wrong = proof.clone();
+            wrong.siblings[1][2] = generate_random_hash();
+            assert!(!wrong.verify(&root, &datum.hash()));
+            let mut wrong = proof.clone();
+            wrong.leaf_count = 17;
+            assert!(!wrong.verify(&root, &datum.hash()));
+            let mut wrong = proof.clone();
+            wrong.siblings[0].pop();
+            assert!(!wrong.verify(&root, &datum.hash()));
+        }
+        assert_eq!(tree.proof(16).unwrap_err(), MerkleError::OutOfBounds(16));
+    }
+
+    #[test]
+    fn padding() {
+        for size in 1..=10 {
+            let input_data: Vec<H256> = (0..size).map(|_| generate_random_hash()).collect();
+            let tree = KaryMerkleTree::new(&input_data, 3);
+            for (idx, datum) in input_data.iter().enumerate() {
+                let proof = tree.proof(idx).unwrap();
+                assert!(proof.verify(&tree.root(), &datum.hash()));
+            }
+        }
+        let empty = KaryMerkleTree::new::<H256>(&[], 4);
+        assert!(empty.root().is_zero());
+        assert_eq!(empty.proof(0).unwrap_err(), MerkleError::EmptyTree);
+    }
+}
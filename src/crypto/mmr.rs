@@ -0,0 +1,233 @@
+use super::hash::H256;
+use super::merkle::{MerkleError, MerkleHasher, Sha256Hasher};
+
+/// A Merkle mountain range (MMR) using SHA256: an append-only accumulator, e.g. over a log of
+/// transactions, with membership proofs. Unlike `MerkleTree`, which is rebuilt from all of its
+/// data, appending a leaf only hashes O(log n) new nodes.
+///
+/// The leaves form a list of perfect binary trees (the peaks) of strictly decreasing heights, one
+/// for each bit set in the number of leaves. Appending a leaf adds a peak of height 0, and merges
+/// the last two peaks while they have the same height. Leaves and internal nodes are hashed as in
+/// `MerkleTree`, so an MMR of 2^k leaves has the same root as a `MerkleTree` over them. The root
+/// of several peaks is obtained by bagging them from the right: the last two peaks are hashed
+/// together, then the one before with the result, and so on. The root of an empty MMR is all
+/// zeros.
+#[derive(Debug, Default, Clone)]
+pub struct Mmr {
+    /// All nodes, each stored after its children (post-order).
+    nodes: Vec<H256>,
+    /// The position in `nodes` and the height of each peak, from left to right.
+    peaks: Vec<(usize, u32)>,
+    /// The number of leaves.
+    leaf_count: usize,
+}
+
+impl Mmr {
+    /// Create an empty MMR.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Returns the number of leaves.
+    pub fn len(&self) -> usize {
+        self.leaf_count
+    }
+
+    /// Returns whether there is no leaf.
+    pub fn is_empty(&self) -> bool {
+        self.leaf_count == 0
+    }
+
+    /// Append a leaf for the given data hash.
+    pub fn append(&mut self, data: H256) {
+        self.peaks.push((self.nodes.len(), 0));
+        self.nodes.push(Sha256Hasher::hash_leaf(&data));
+        while let [.., (left, left_height), (right, right_height)] = self.peaks[..] {
+            if left_height != right_height {
+                break;
+            }
+            let parent = Sha256Hasher::hash_nodes(&self.nodes[left], &self.nodes[right]);
+            self.peaks.truncate(self.peaks.len() - 2);
+            self.peaks.push((self.nodes.len(), left_height + 1));
+            self.nodes.push(parent);
+        }
+        self.leaf_count += 1;
+    }
+
+    /// Returns the root, i.e. the bagged peaks.
+    pub fn root(&self) -> H256 {
+        let peaks: Vec<H256> = self.peaks.iter().map(|(pos, _)| self.nodes[*pos]).collect();
+        bag_peaks(&peaks)
+    }
+
+    /// Generate a proof that the leaf at the given index is in the MMR.
+    pub fn prove(&self, leaf_index: usize) -> Result<MmrProof, MerkleError> {
+        if self.is_empty() {
+            return Err(MerkleError::EmptyTree);
+        }
+        if leaf_index >= self.leaf_count {
+            return Err(MerkleError::OutOfBounds(leaf_index));
+        }
+        // find the peak whose leaves include the given one
+        let mut first_leaf = 0;
+        let mut peak = 0;
+        while first_leaf + (1 << self.peaks[peak].1) <= leaf_index {
+            first_leaf += 1 << self.peaks[peak].1;
+            peak += 1;
+        }
+        // descend from the peak to the leaf, collecting the siblings
+        let (mut pos, mut height) = self.peaks[peak];
+        let offset = leaf_index - first_leaf;
+        let mut siblings = Vec::with_capacity(height as usize);
+        while height > 0 {
+            // a subtree of height h has 2^(h+1) - 1 nodes and is stored before its parent
+            let right = pos - 1;
+            let left = pos - (1 << height);
+            height -= 1;
+            if offset & (1 << height) == 0 {
+                siblings.push(self.nodes[right]);
+                pos = left;
+            } else {
+                siblings.push(self.nodes[left]);
+                pos = right;
+            }
+        }
+        siblings.reverse();
+        let peaks = self
+            .peaks
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| *index != peak)
+            .map(|(_, (pos, _))| self.nodes[*pos])
+            .collect();
+        Ok(MmrProof {
+            leaf_index,
+            leaf_count: self.leaf_count,
+            siblings,
+            peaks,
+        })
+    }
+}
+
+/// A proof that a leaf is in an MMR.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct MmrProof {
+    /// Index of the leaf.
+    pub leaf_index: usize,
+    /// Number of leaves in the MMR.
+    pub leaf_count: usize,
+    /// The sibling hashes on the path from the leaf to its peak, bottom-up.
+    pub siblings: Vec<H256>,
+    /// The other peaks, from left to right.
+    pub peaks: Vec<H256>,
+}
+
+impl MmrProof {
+    /// Verify that the data hash is at the leaf index of an MMR with the given root.
+    pub fn verify(&self, root: &H256, data: &H256) -> bool {
+        if self.leaf_index >= self.leaf_count {
+            return false;
+        }
+        // the peaks have the heights of the bits set in the number of leaves, highest first
+        let heights: Vec<u32> = (0..usize::MAX.count_ones())
+            .rev()
+            .filter(|height| self.leaf_count & (1 << height) != 0)
+            .collect();
+        if self.peaks.len() + 1 != heights.len() {
+            return false;
+        }
+        let mut first_leaf = 0;
+        let mut peak = 0;
+        while first_leaf + (1 << heights[peak]) <= self.leaf_index {
+            first_leaf += 1 << heights[peak];
+            peak += 1;
+        }
+        if self.siblings.len() != heights[peak] as usize {
+            return false;
+        }
+        let offset = self.leaf_index - first_leaf;
+        let mut acc = Sha256Hasher::hash_leaf(data);
+        for (height, sibling) in self.siblings.iter().enumerate() {
+            acc = if offset & (1 << height) == 0 {
+                Sha256Hasher::hash_nodes(&acc, sibling)
+            } else {
+                Sha256Hasher::hash_nodes(sibling, &acc)
+            };
+        }
+        let mut peaks = self.peaks.clone();
+        peaks.insert(peak, acc);
+        bag_peaks(&peaks).ct_eq(root)
+    }
+}
+
+/// Returns the root of an MMR with the given peaks, bagged from the right.
+fn bag_peaks(peaks: &[H256]) -> H256 {
+    match peaks.split_last() {
+        Some((last, rest)) => rest
+            .iter()
+            .rev()
+            .fold(*last, |acc, peak| Sha256Hasher::hash_nodes(peak, &acc)),
+        None => H256::zero(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::hash::tests::generate_random_hash;
+    use crate::crypto::merkle::MerkleTree;
+
+    #[test]
+    fn append_and_prove() {
+        let data: Vec<H256> = (0..20).map(|_| generate_random_hash()).collect();
+        let mut mmr = Mmr::new();
+        assert_eq!(mmr.root(), H256::zero());
+        assert_eq!(mmr.prove(0), Err(MerkleError::EmptyTree));
+        for (count, datum) in data.iter().enumerate() {
+            mmr.append(*datum);
+            let count = count + 1;
+            assert_eq!(mmr.len(), count);
+            assert_eq!(mmr.peaks.len(), count.count_ones() as usize);
+            let root = mmr.root();
+            for index in (0..count).step_by(3).chain(Some(count - 1)) {
+                let proof = mmr.prove(index).unwrap();
+                assert!(proof.verify(&root, &data[index]));
+                assert!(!proof.verify(&root, &generate_random_hash()));
+                if count > 1 {
+                    let mut moved = proof.clone();
+                    moved.leaf_index = (index + 1) % count;
+                    assert!(!moved.verify(&root, &data[index]));
+                }
+            }
+            assert_eq!(mmr.prove(count), Err(MerkleError::OutOfBounds(count)));
+        }
+    }
+
+    #[test]
+    fn old_proof() {
+        let data: Vec<H256> = (0..6).map(|_| generate_random_hash()).collect();
+        let mut mmr = Mmr::new();
+        for datum in &data[..5] {
+            mmr.append(*datum);
+        }
+        let proof = mmr.prove(2).unwrap();
+        let root = mmr.root();
+        mmr.append(data[5]);
+        assert!(proof.verify(&root, &data[2]));
+        // the proof is for the MMR before the append
+        assert!(!proof.verify(&mmr.root(), &data[2]));
+        assert!(mmr.prove(2).unwrap().verify(&mmr.root(), &data[2]));
+    }
+
+    #[test]
+    fn same_root_as_merkle_tree() {
+        for count in &[1, 2, 4, 8, 16] {
+            let data: Vec<H256> = (0..*count).map(|_| generate_random_hash()).collect();
+            let mut mmr = Mmr::new();
+            for datum in &data {
+                mmr.append(*datum);
+            }
+            assert_eq!(mmr.root(), MerkleTree::from_leaf_hashes(&data).root());
+        }
+    }
+}
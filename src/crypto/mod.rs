@@ -1,2 +1,6 @@
 pub mod hash;
+pub mod kary_merkle;
 pub mod merkle;
+pub mod mmr;
+pub mod sign;
+pub mod sparse_merkle;
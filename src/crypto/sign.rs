@@ -0,0 +1,140 @@
+use ed25519_dalek::{Keypair, PublicKey, SecretKey, SignatureError};
+use ed25519_dalek::{KEYPAIR_LENGTH, PUBLIC_KEY_LENGTH};
+
+/// An error when decoding a key.
+#[derive(Debug)]
+pub enum KeyError {
+    /// The encoding does not have the expected length.
+    InvalidLength { expected: usize, actual: usize },
+    /// The public key of a key pair does not belong to its secret key.
+    MismatchedPublicKey,
+    /// The key is not valid.
+    Malformed(SignatureError),
+}
+
+impl std::fmt::Display for KeyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            KeyError::InvalidLength { expected, actual } => {
+                write!(f, "expected {} bytes of key, got {}", expected, actual)
+            }
+            KeyError::MismatchedPublicKey => write!(f, "public key does not match secret key"),
+            KeyError::Malformed(ref e) => write!(f, "malformed key: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for KeyError {}
+
+/// Derive a key pair from a 32-byte seed, which is used as the Ed25519 secret key. The same seed
+/// always gives the same key pair, and hence the same signatures.
+pub fn from_seed(seed: &[u8; 32]) -> Keypair {
+    // any 32 bytes make a valid secret key
+    let secret = SecretKey::from_bytes(seed).unwrap();
+    let public: PublicKey = (&secret).into();
+    Keypair { secret, public }
+}
+
+/// Encode a key pair as its secret key followed by its public key.
+pub fn keypair_to_bytes(keypair: &Keypair) -> [u8; KEYPAIR_LENGTH] {
+    keypair.to_bytes()
+}
+
+/// Decode a key pair encoded by `keypair_to_bytes`, checking that the public key belongs to the
+/// secret key.
+pub fn keypair_from_bytes(bytes: &[u8]) -> Result<Keypair, KeyError> {
+    if bytes.len() != KEYPAIR_LENGTH {
+        return Err(KeyError::InvalidLength {
+            expected: KEYPAIR_LENGTH,
+            actual: bytes.len(),
+        });
+    }
+    let keypair = Keypair::from_bytes(bytes).map_err(KeyError::Malformed)?;
+    let public: PublicKey = (&keypair.secret).into();
+    if public != keypair.public {
+        return Err(KeyError::MismatchedPublicKey);
+    }
+    Ok(keypair)
+}
+
+/// Encode a public key.
+pub fn pubkey_to_bytes(pubkey: &PublicKey) -> [u8; PUBLIC_KEY_LENGTH] {
+    pubkey.to_bytes()
+}
+
+/// Decode a public key encoded by `pubkey_to_bytes`.
+pub fn pubkey_from_bytes(bytes: &[u8]) -> Result<PublicKey, KeyError> {
+    if bytes.len() != PUBLIC_KEY_LENGTH {
+        return Err(KeyError::InvalidLength {
+            expected: PUBLIC_KEY_LENGTH,
+            actual: bytes.len(),
+        });
+    }
+    PublicKey::from_bytes(bytes).map_err(KeyError::Malformed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::NETWORK_ID;
+    use crate::transaction::tests::generate_signed_transaction;
+
+    #[test]
+    fn from_seed() {
+        let seed = [7u8; 32];
+        let first = super::from_seed(&seed);
+        let second = super::from_seed(&seed);
+        assert_eq!(first.public, second.public);
+        let message = b"prism";
+        assert_eq!(
+            first.sign(message).to_bytes()[..],
+            second.sign(message).to_bytes()[..]
+        );
+        assert!(second.public.verify(message, &first.sign(message)).is_ok());
+        let other = super::from_seed(&[8u8; 32]);
+        assert_ne!(first.public, other.public);
+    }
+
+    #[test]
+    fn keypair_bytes() {
+        let keypair = super::from_seed(&[1u8; 32]);
+        let transaction = generate_signed_transaction(&[keypair]);
+        let keypair = super::from_seed(&[1u8; 32]);
+
+        let loaded = keypair_from_bytes(&keypair_to_bytes(&keypair)).unwrap();
+        assert_eq!(loaded.public, keypair.public);
+        let message = transaction.signing_bytes(NETWORK_ID);
+        assert_eq!(
+            loaded.sign(&message).to_bytes()[..],
+            transaction.authorization[0].signature[..]
+        );
+        let pubkey = pubkey_from_bytes(&pubkey_to_bytes(&loaded.public)).unwrap();
+        assert!(pubkey.verify(&message, &loaded.sign(&message)).is_ok());
+    }
+
+    #[test]
+    fn invalid_bytes() {
+        let keypair = super::from_seed(&[1u8; 32]);
+        let bytes = keypair_to_bytes(&keypair);
+        match keypair_from_bytes(&bytes[..63]) {
+            Err(KeyError::InvalidLength { expected, actual }) => {
+                assert_eq!((expected, actual), (64, 63))
+            }
+            _ => panic!("expected a length error"),
+        }
+        match pubkey_from_bytes(&bytes[32..40]) {
+            Err(KeyError::InvalidLength { expected, actual }) => {
+                assert_eq!((expected, actual), (32, 8))
+            }
+            _ => panic!("expected a length error"),
+        }
+        // a public key from another key pair
+        let mut mismatched = bytes;
+        let other = super::from_seed(&[2u8; 32]);
+        mismatched[32..].copy_from_slice(other.public.as_bytes());
+        match keypair_from_bytes(&mismatched) {
+            Err(KeyError::MismatchedPublicKey) => {}
+            _ => panic!("expected a mismatch error"),
+        }
+    }
+}
@@ -0,0 +1,273 @@
+use super::hash::H256;
+use super::merkle::{MerkleHasher, Sha256Hasher};
+use std::collections::HashMap;
+
+/// The depth of a sparse Merkle tree, i.e. the number of bits in a key.
+pub const DEPTH: usize = 256;
+
+/// A sparse Merkle tree keyed by `H256`, using SHA256. It has one leaf for each of the 2^256 keys,
+/// most of which are empty, and can prove both that a key is in the tree and that it is not.
+///
+/// The path from the root to the leaf of a key follows the bits of the key, most significant bit
+/// first, going to the right child on a 1. An empty leaf is all zeros, and a non-empty leaf is the
+/// leaf hash of its value. Only the nodes above non-empty leaves are stored; every other subtree is
+/// empty and its root is looked up in a table of empty-subtree hashes.
+#[derive(Debug, Clone)]
+pub struct SparseMerkleTree {
+    /// The value of each non-empty leaf.
+    values: HashMap<H256, H256>,
+    /// The non-empty nodes, keyed by their height (0 for leaves) and the key of any leaf below
+    /// them, with the bits below that height cleared.
+    nodes: HashMap<(usize, H256), H256>,
+    /// The root of an empty subtree of each height.
+    empty: Vec<H256>,
+}
+
+impl Default for SparseMerkleTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SparseMerkleTree {
+    /// Create an empty tree.
+    pub fn new() -> Self {
+        let mut empty = Vec::with_capacity(DEPTH + 1);
+        empty.push(H256::default());
+        for height in 0..DEPTH {
+            let node = Sha256Hasher::hash_nodes(&empty[height], &empty[height]);
+            empty.push(node);
+        }
+        Self {
+            values: HashMap::new(),
+            nodes: HashMap::new(),
+            empty,
+        }
+    }
+
+    /// Returns the root of the tree.
+    pub fn root(&self) -> H256 {
+        self.node(DEPTH, &H256::default())
+    }
+
+    /// Returns the number of non-empty leaves.
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Returns whether all leaves are empty.
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Returns the value at the given key.
+    pub fn get(&self, key: &H256) -> Option<&H256> {
+        self.values.get(key)
+    }
+
+    /// Set the value at the given key, and rehash the path from its leaf to the root.
+    pub fn insert(&mut self, key: H256, value: H256) {
+        self.values.insert(key, value);
+        let mut acc = Sha256Hasher::hash_leaf(&value);
+        self.nodes.insert((0, key), acc);
+        for height in 0..DEPTH {
+            let bit = DEPTH - 1 - height;
+            let sibling = self.node(height, &flip_bit(&clear_low_bits(&key, height), bit));
+            acc = if get_bit(&key, bit) {
+                Sha256Hasher::hash_nodes(&sibling, &acc)
+            } else {
+                Sha256Hasher::hash_nodes(&acc, &sibling)
+            };
+            self.nodes
+                .insert((height + 1, clear_low_bits(&key, height + 1)), acc);
+        }
+    }
+
+    /// Generate a proof of the value at the given key, or of its absence.
+    pub fn prove(&self, key: &H256) -> SparseMerkleProof {
+        let siblings = (0..DEPTH)
+            .map(|height| {
+                let bit = DEPTH - 1 - height;
+                self.node(height, &flip_bit(&clear_low_bits(key, height), bit))
+            })
+            .collect();
+        SparseMerkleProof {
+            value: self.values.get(key).cloned(),
+            siblings,
+        }
+    }
+
+    /// Returns the node at the given height above the leaf of the given key, whose bits below that
+    /// height must be cleared.
+    fn node(&self, height: usize, prefix: &H256) -> H256 {
+        match self.nodes.get(&(height, *prefix)) {
+            Some(node) => *node,
+            None => self.empty[height],
+        }
+    }
+}
+
+/// A proof of the value at a key of a sparse Merkle tree, or of the absence of a value.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SparseMerkleProof {
+    /// The value at the key, or `None` if the leaf of the key is empty.
+    pub value: Option<H256>,
+    /// The siblings of the nodes on the path from the leaf to the root, bottom-up.
+    pub siblings: Vec<H256>,
+}
+
+impl SparseMerkleProof {
+    /// Verify that the tree with the given root has the given value at the given key. If `value`
+    /// is `None`, verify that the key is absent from the tree.
+    pub fn verify(&self, root: &H256, key: &H256, value: Option<&H256>) -> bool {
+        if self.value.as_ref() != value || self.siblings.len() != DEPTH {
+            return false;
+        }
+        let mut acc = match value {
+            Some(value) => Sha256Hasher::hash_leaf(value),
+            None => H256::default(),
+        };
+        for (height, sibling) in self.siblings.iter().enumerate() {
+            acc = if get_bit(key, DEPTH - 1 - height) {
+                Sha256Hasher::hash_nodes(sibling, &acc)
+            } else {
+                Sha256Hasher::hash_nodes(&acc, sibling)
+            };
+        }
+        acc.ct_eq(root)
+    }
+}
+
+/// Returns the given bit of a key, where bit 0 is the most significant one.
+fn get_bit(key: &H256, bit: usize) -> bool {
+    let bytes: &[u8] = key.as_ref();
+    (bytes[bit / 8] >> (7 - bit % 8)) & 1 == 1
+}
+
+/// Returns the key with the given bit flipped, where bit 0 is the most significant one.
+fn flip_bit(key: &H256, bit: usize) -> H256 {
+    let mut bytes: [u8; 32] = key.into();
+    bytes[bit / 8] ^= 1 << (7 - bit % 8);
+    bytes.into()
+}
+
+/// Returns the key with its `count` least significant bits cleared.
+fn clear_low_bits(key: &H256, count: usize) -> H256 {
+    let mut bytes: [u8; 32] = key.into();
+    for bit in DEPTH - count..DEPTH {
+        bytes[bit / 8] &= !(1 << (7 - bit % 8));
+    }
+    bytes.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::hash::tests::generate_random_hash;
+    use crate::transaction::CoinId;
+
+    fn coin_key(coin: &CoinId) -> H256 {
+        ring::digest::digest(&ring::digest::SHA256, &bincode::serialize(coin).unwrap()).into()
+    }
+
+    #[test]
+    fn empty() {
+        let tree = SparseMerkleTree::new();
+        assert!(tree.is_empty());
+        let key = generate_random_hash();
+        let proof = tree.prove(&key);
+        assert_eq!(proof.value, None);
+        assert!(proof.verify(&tree.root(), &key, None));
+    }
+
+    #[test]
+    fn insert() {
+        let mut tree = SparseMerkleTree::new();
+        let empty_root = tree.root();
+        let key = generate_random_hash();
+        let value = generate_random_hash();
+        tree.insert(key, value);
+        assert_eq!(tree.len(), 1);
+        assert_eq!(tree.get(&key), Some(&value));
+        assert_ne!(tree.root(), empty_root);
+
+        // the root does not depend on the order of insertion
+        let other_key = generate_random_hash();
+        let other_value = generate_random_hash();
+        tree.insert(other_key, other_value);
+        let mut reordered = SparseMerkleTree::new();
+        reordered.insert(other_key, other_value);
+        reordered.insert(key, value);
+        assert_eq!(tree.root(), reordered.root());
+
+        // overwriting a value
+        let new_value = generate_random_hash();
+        tree.insert(key, new_value);
+        assert_eq!(tree.len(), 2);
+        assert_eq!(tree.get(&key), Some(&new_value));
+        assert_ne!(tree.root(), reordered.root());
+    }
+
+    #[test]
+    fn membership() {
+        let mut tree = SparseMerkleTree::new();
+        let hash = generate_random_hash();
+        let coins: Vec<CoinId> = (0..4).map(|index| CoinId { hash, index }).collect();
+        for coin in &coins {
+            tree.insert(coin_key(coin), hash);
+        }
+        let root = tree.root();
+        for coin in &coins {
+            let key = coin_key(coin);
+            let proof = tree.prove(&key);
+            assert_eq!(proof.value, Some(hash));
+            assert!(proof.verify(&root, &key, Some(&hash)));
+            assert!(!proof.verify(&root, &key, None));
+            assert!(!proof.verify(&root, &key, Some(&generate_random_hash())));
+            assert!(!proof.verify(&generate_random_hash(), &key, Some(&hash)));
+        }
+    }
+
+    #[test]
+    fn non_membership() {
+        let mut tree = SparseMerkleTree::new();
+        let hash = generate_random_hash();
+        for index in 0..4 {
+            tree.insert(coin_key(&CoinId { hash, index }), hash);
+        }
+        let root = tree.root();
+        let absent = coin_key(&CoinId { hash, index: 4 });
+        let proof = tree.prove(&absent);
+        assert_eq!(proof.value, None);
+        assert!(proof.verify(&root, &absent, None));
+        assert!(!proof.verify(&root, &absent, Some(&hash)));
+
+        // the proof does not carry over to a present key
+        let present = coin_key(&CoinId { hash, index: 0 });
+        let mut forged = proof.clone();
+        forged.value = None;
+        assert!(!forged.verify(&root, &present, None));
+
+        // nor to a tree where the key has been inserted
+        tree.insert(absent, hash);
+        assert!(!proof.verify(&tree.root(), &absent, None));
+    }
+
+    #[test]
+    fn neighbouring_keys() {
+        // keys that only differ in their last bit share all nodes but the leaves
+        let mut tree = SparseMerkleTree::new();
+        let left: H256 = [0u8; 32].into();
+        let mut right_bytes = [0u8; 32];
+        right_bytes[31] = 1;
+        let right: H256 = right_bytes.into();
+        let value = generate_random_hash();
+        tree.insert(left, value);
+        let proof = tree.prove(&right);
+        assert!(proof.verify(&tree.root(), &right, None));
+        assert_eq!(proof.siblings[0], Sha256Hasher::hash_leaf(&value));
+        tree.insert(right, value);
+        let proof = tree.prove(&right);
+        assert!(proof.verify(&tree.root(), &right, Some(&value)));
+    }
+}
@@ -0,0 +1,444 @@
+use crate::transaction::{CoinId, Output, Transaction};
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// An in-memory set of unspent transaction outputs (UTXO), to which transactions are applied.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct UtxoSet {
+    coins: HashMap<CoinId, Output>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum LedgerError {
+    /// The coin is spent but not in the set, e.g. it is spent twice.
+    MissingCoin(CoinId),
+    /// The coin is created but already in the set.
+    DuplicateCoin(CoinId),
+    /// The transaction at this index of a sequence takes no input, but is not a coinbase at the
+    /// start of the sequence.
+    InvalidCoinbase(usize),
+}
+
+impl fmt::Display for LedgerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LedgerError::MissingCoin(ref c) => {
+                write!(f, "coin {}:{} is not in the UTXO set", c.hash, c.index)
+            }
+            LedgerError::DuplicateCoin(ref c) => {
+                write!(f, "coin {}:{} is already in the UTXO set", c.hash, c.index)
+            }
+            LedgerError::InvalidCoinbase(index) => {
+                write!(
+                    f,
+                    "transaction {} takes no input but is not the coinbase",
+                    index
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for LedgerError {}
+
+impl UtxoSet {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Returns whether the coin is unspent.
+    pub fn contains(&self, coin: &CoinId) -> bool {
+        self.coins.contains_key(coin)
+    }
+
+    /// Returns the output of an unspent coin.
+    pub fn get(&self, coin: &CoinId) -> Option<&Output> {
+        self.coins.get(coin)
+    }
+
+    /// Returns the number of unspent coins.
+    pub fn len(&self) -> usize {
+        self.coins.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.coins.is_empty()
+    }
+
+    /// Insert an unspent coin, e.g. from the genesis state.
+    pub fn insert(&mut self, coin: CoinId, output: Output) -> Result<(), LedgerError> {
+        if self.coins.contains_key(&coin) {
+            return Err(LedgerError::DuplicateCoin(coin));
+        }
+        self.coins.insert(coin, output);
+        Ok(())
+    }
+
+    /// Insert all coins of another set, e.g. one computed in parallel over a disjoint part of the
+    /// state. If any of its coins is already in this set, the set is left unchanged.
+    pub fn merge(&mut self, other: &UtxoSet) -> Result<(), LedgerError> {
+        if let Some(coin) = other.coins.keys().find(|x| self.coins.contains_key(x)) {
+            return Err(LedgerError::DuplicateCoin(*coin));
+        }
+        self.coins
+            .extend(other.coins.iter().map(|(coin, output)| (*coin, *output)));
+        Ok(())
+    }
+
+    /// Apply a transaction: remove the coins it spends and insert the coins it creates, whose IDs
+    /// are the hash of the transaction and the index of the output. If any spent coin is missing
+    /// (including a coin spent twice by the transaction) or any created coin already exists, the
+    /// set is left unchanged.
+    pub fn apply(&mut self, transaction: &Transaction) -> Result<(), LedgerError> {
+        let mut spent: HashSet<CoinId> = HashSet::new();
+        for input in &transaction.input {
+            if !self.coins.contains_key(&input.coin) || !spent.insert(input.coin) {
+                return Err(LedgerError::MissingCoin(input.coin));
+            }
+        }
+        let created = created_coins(transaction);
+        for (coin, _) in &created {
+            if self.coins.contains_key(coin) {
+                return Err(LedgerError::DuplicateCoin(*coin));
+            }
+        }
+        for coin in &spent {
+            self.coins.remove(coin);
+        }
+        self.coins.extend(created);
+        Ok(())
+    }
+
+    /// Roll back a transaction applied by `apply`: remove the coins it created, and re-insert the
+    /// coins it spent, rebuilt from the value and owner stored in its inputs. If any created coin
+    /// is missing (e.g. it has been spent since) or any spent coin already exists, the set is left
+    /// unchanged.
+    pub fn undo(&mut self, transaction: &Transaction) -> Result<(), LedgerError> {
+        let created = created_coins(transaction);
+        for (coin, _) in &created {
+            if !self.coins.contains_key(coin) {
+                return Err(LedgerError::MissingCoin(*coin));
+            }
+        }
+        for input in &transaction.input {
+            if self.coins.contains_key(&input.coin) {
+                return Err(LedgerError::DuplicateCoin(input.coin));
+            }
+        }
+        for (coin, _) in &created {
+            self.coins.remove(coin);
+        }
+        for input in &transaction.input {
+            let output = Output {
+                value: input.value,
+                recipient: input.owner,
+            };
+            self.coins.insert(input.coin, output);
+        }
+        Ok(())
+    }
+}
+
+/// Check that a list of transactions, e.g. those of a block, can be applied in order to an empty
+/// UTXO set, so that a transaction may spend the coins created by an earlier one, but not a coin
+/// that does not exist or that an earlier one spent. Every transaction must take inputs, except for
+/// a coinbase as the first transaction.
+pub fn validate_sequence(txs: &[Transaction]) -> Result<(), LedgerError> {
+    validate_sequence_from(&UtxoSet::new(), txs)
+}
+
+/// Same as `validate_sequence`, but starting from the given UTXO set, which is left unchanged.
+pub fn validate_sequence_from(utxo: &UtxoSet, txs: &[Transaction]) -> Result<(), LedgerError> {
+    let mut utxo = utxo.clone();
+    for (index, tx) in txs.iter().enumerate() {
+        if tx.input.is_empty() && (index > 0 || !tx.is_coinbase()) {
+            return Err(LedgerError::InvalidCoinbase(index));
+        }
+        utxo.apply(tx)?;
+    }
+    Ok(())
+}
+
+/// Returns the coins created by a transaction.
+fn created_coins(transaction: &Transaction) -> Vec<(CoinId, Output)> {
+    transaction
+        .output_coin_ids()
+        .into_iter()
+        .zip(transaction.output.iter().cloned())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::hash::tests::generate_random_hash;
+    use crate::crypto::hash::Hashable;
+    use crate::transaction::{Input, TransactionBuilder};
+
+    fn spend(coin: CoinId, output: &Output) -> Input {
+        Input {
+            coin,
+            value: output.value,
+            owner: output.recipient,
+        }
+    }
+
+    #[test]
+    fn apply_chain() {
+        let alice = generate_random_hash();
+        let bob = generate_random_hash();
+        let genesis_coin = CoinId {
+            hash: generate_random_hash(),
+            index: 0,
+        };
+        let genesis_output = Output {
+            value: 100,
+            recipient: alice,
+        };
+        let mut utxo = UtxoSet::new();
+        utxo.insert(genesis_coin, genesis_output).unwrap();
+
+        let first = TransactionBuilder::new()
+            .add_input(spend(genesis_coin, &genesis_output))
+            .add_output(60, bob)
+            .add_output(40, alice)
+            .build();
+        utxo.apply(&first).unwrap();
+        let first_coins = created_coins(&first);
+        assert!(!utxo.contains(&genesis_coin));
+        assert_eq!(utxo.len(), 2);
+        assert_eq!(first_coins[1].0.hash, first.hash());
+        assert_eq!(first_coins[1].0.index, 1);
+
+        let second = TransactionBuilder::new()
+            .add_input(spend(first_coins[0].0, &first_coins[0].1))
+            .add_output(60, alice)
+            .build();
+        utxo.apply(&second).unwrap();
+        let second_coin = CoinId {
+            hash: second.hash(),
+            index: 0,
+        };
+        assert_eq!(utxo.len(), 2);
+        assert!(utxo.contains(&first_coins[1].0));
+        assert!(!utxo.contains(&first_coins[0].0));
+        assert_eq!(
+            utxo.get(&second_coin),
+            Some(&Output {
+                value: 60,
+                recipient: alice
+            })
+        );
+
+        // spending a coin again is a double spend
+        let before = utxo.clone();
+        assert_eq!(
+            utxo.apply(&second),
+            Err(LedgerError::MissingCoin(first_coins[0].0))
+        );
+        assert_eq!(utxo, before);
+    }
+
+    #[test]
+    fn undo() {
+        let alice = generate_random_hash();
+        let mut utxo = UtxoSet::new();
+        let mut inputs = vec![];
+        for value in 1..=3 {
+            let coin = CoinId {
+                hash: generate_random_hash(),
+                index: value,
+            };
+            let output = Output {
+                value: u64::from(value),
+                recipient: alice,
+            };
+            utxo.insert(coin, output).unwrap();
+            inputs.push(spend(coin, &output));
+        }
+        let original = utxo.clone();
+        let transaction = TransactionBuilder::new()
+            .add_input(inputs[0])
+            .add_input(inputs[2])
+            .add_output(3, generate_random_hash())
+            .add_output(1, alice)
+            .build();
+        utxo.apply(&transaction).unwrap();
+        assert_ne!(utxo, original);
+        utxo.undo(&transaction).unwrap();
+        assert_eq!(utxo, original);
+
+        // a transaction that has not been applied cannot be undone
+        let coin = CoinId {
+            hash: transaction.hash(),
+            index: 0,
+        };
+        assert_eq!(utxo.undo(&transaction), Err(LedgerError::MissingCoin(coin)));
+        assert_eq!(utxo, original);
+    }
+
+    #[test]
+    fn double_spend_within_transaction() {
+        let coin = CoinId {
+            hash: generate_random_hash(),
+            index: 0,
+        };
+        let output = Output {
+            value: 10,
+            recipient: generate_random_hash(),
+        };
+        let mut utxo = UtxoSet::new();
+        utxo.insert(coin, output).unwrap();
+        assert_eq!(
+            utxo.insert(coin, output),
+            Err(LedgerError::DuplicateCoin(coin))
+        );
+        let transaction = TransactionBuilder::new()
+            .add_input(spend(coin, &output))
+            .add_input(spend(coin, &output))
+            .add_output(20, generate_random_hash())
+            .build();
+        assert_eq!(
+            utxo.apply(&transaction),
+            Err(LedgerError::MissingCoin(coin))
+        );
+        assert!(utxo.contains(&coin));
+    }
+
+    #[test]
+    fn merge() {
+        let coin = |index| CoinId {
+            hash: generate_random_hash(),
+            index,
+        };
+        let output = Output {
+            value: 10,
+            recipient: generate_random_hash(),
+        };
+        let mut left = UtxoSet::new();
+        let mut right = UtxoSet::new();
+        let left_coins: Vec<CoinId> = (0..3).map(coin).collect();
+        let right_coins: Vec<CoinId> = (0..2).map(coin).collect();
+        for c in &left_coins {
+            left.insert(*c, output).unwrap();
+        }
+        for c in &right_coins {
+            right.insert(*c, output).unwrap();
+        }
+
+        // disjoint sets
+        let mut merged = left.clone();
+        merged.merge(&right).unwrap();
+        assert_eq!(merged.len(), 5);
+        assert!(left_coins
+            .iter()
+            .chain(&right_coins)
+            .all(|c| merged.contains(c)));
+
+        // overlapping sets
+        right.insert(left_coins[1], output).unwrap();
+        let before = left.clone();
+        assert_eq!(
+            left.merge(&right),
+            Err(LedgerError::DuplicateCoin(left_coins[1]))
+        );
+        assert_eq!(left, before);
+    }
+
+    #[test]
+    fn validate_sequence() {
+        let alice = generate_random_hash();
+        let coinbase = Transaction::coinbase(alice, 50, generate_random_hash());
+        let coin = CoinId {
+            hash: coinbase.hash(),
+            index: 0,
+        };
+        let output = coinbase.output[0];
+        let spending = TransactionBuilder::new()
+            .add_input(spend(coin, &output))
+            .add_output(50, generate_random_hash())
+            .build();
+        assert_eq!(
+            super::validate_sequence(&[coinbase.clone(), spending.clone()]),
+            Ok(())
+        );
+        // out of order
+        assert_eq!(
+            super::validate_sequence(&[spending.clone(), coinbase.clone()]),
+            Err(LedgerError::MissingCoin(coin))
+        );
+        // a second coinbase
+        let second = Transaction::coinbase(alice, 50, generate_random_hash());
+        assert_eq!(
+            super::validate_sequence(&[coinbase.clone(), second]),
+            Err(LedgerError::InvalidCoinbase(1))
+        );
+        // a transaction without inputs that is not a coinbase
+        let free = TransactionBuilder::new()
+            .add_output(25, alice)
+            .add_output(25, alice)
+            .build();
+        assert_eq!(
+            super::validate_sequence(&[free]),
+            Err(LedgerError::InvalidCoinbase(0))
+        );
+
+        // spending a coin the first transaction never created
+        let missing = CoinId {
+            hash: coinbase.hash(),
+            index: 1,
+        };
+        let invalid = TransactionBuilder::new()
+            .add_input(spend(missing, &output))
+            .add_output(50, generate_random_hash())
+            .build();
+        assert_eq!(
+            super::validate_sequence(&[coinbase.clone(), invalid]),
+            Err(LedgerError::MissingCoin(missing))
+        );
+
+        // a double spend
+        let mut double_spend = spending.clone();
+        double_spend.output[0].value = 49;
+        assert_eq!(
+            super::validate_sequence(&[coinbase.clone(), spending.clone(), double_spend]),
+            Err(LedgerError::MissingCoin(coin))
+        );
+
+        // from an existing UTXO set, which is left unchanged
+        let mut utxo = UtxoSet::new();
+        utxo.insert(coin, output).unwrap();
+        let before = utxo.clone();
+        assert_eq!(validate_sequence_from(&utxo, &[spending]), Ok(()));
+        assert_eq!(utxo, before);
+    }
+
+    #[test]
+    fn equal_coinbases() {
+        // two blocks paying the same reward to the same miner
+        let miner = generate_random_hash();
+        let first = Transaction::coinbase(miner, 50, generate_random_hash());
+        let second = Transaction::coinbase(miner, 50, generate_random_hash());
+        let mut utxo = UtxoSet::new();
+        utxo.apply(&first).unwrap();
+        utxo.apply(&second).unwrap();
+        assert_eq!(utxo.len(), 2);
+    }
+
+    #[test]
+    fn duplicate_output() {
+        let transaction = Transaction::coinbase(generate_random_hash(), 10, generate_random_hash());
+        let mut utxo = UtxoSet::new();
+        utxo.apply(&transaction).unwrap();
+        let coin = CoinId {
+            hash: transaction.hash(),
+            index: 0,
+        };
+        assert_eq!(
+            utxo.apply(&transaction),
+            Err(LedgerError::DuplicateCoin(coin))
+        );
+        assert_eq!(utxo.len(), 1);
+    }
+}
@@ -14,6 +14,7 @@ pub mod config;
 pub mod crypto;
 pub mod experiment;
 pub mod handler;
+pub mod ledger;
 pub mod ledger_manager;
 pub mod miner;
 pub mod network;
@@ -10,13 +10,14 @@ use prism::blockchain::BlockChain;
 use prism::blockdb::BlockDatabase;
 use prism::config::BlockchainConfig;
 use prism::crypto::hash::H256;
+use prism::crypto::sign;
 use prism::experiment::transaction_generator::TransactionGenerator;
 use prism::ledger_manager::LedgerManager;
 use prism::miner;
 use prism::miner::memory_pool::MemoryPool;
 use prism::network::server;
 use prism::network::worker;
-use prism::transaction::Address;
+use prism::transaction::{address_from_pubkey, Address};
 use prism::utxodb::UtxoDatabase;
 use prism::visualization::Server as VisualizationServer;
 use prism::wallet::Wallet;
@@ -73,11 +74,7 @@ fn main() {
             let base64_encoded = base64::encode(&keypair.to_bytes().to_vec());
             println!("{}", base64_encoded);
             if m.is_present("display_address") {
-                let addr: Address = ring::digest::digest(
-                    &ring::digest::SHA256,
-                    &keypair.public.as_bytes().as_ref(),
-                )
-                .into();
+                let addr: Address = address_from_pubkey(&keypair.public);
                 let base64_encoded = base64::encode(&addr);
                 eprintln!("{}", base64_encoded);
             }
@@ -221,7 +218,13 @@ fn main() {
                     process::exit(1);
                 }
             };
-            let keypair = Keypair::from_bytes(&decoded).unwrap();
+            let keypair = match sign::keypair_from_bytes(&decoded) {
+                Ok(k) => k,
+                Err(e) => {
+                    error!("Error decoding key pair at {}: {}", &key_path, &e);
+                    process::exit(1);
+                }
+            };
             match wallet.load_keypair(keypair) {
                 Ok(a) => info!("Loaded key pair for address {}", &a),
                 Err(e) => {
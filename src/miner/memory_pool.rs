@@ -1,4 +1,5 @@
 use crate::crypto::hash::{Hashable, H256};
+use crate::experiment::performance_counter::PayloadSize;
 use crate::transaction::{CoinId, Input, Transaction};
 use std::collections::BTreeMap;
 use std::collections::HashMap;
@@ -19,6 +20,45 @@ pub struct MemoryPool {
     by_input: HashMap<Input, H256>,
     /// Storage for order by storage index, it is equivalent to FIFO
     by_storage_index: BTreeMap<u64, H256>,
+    /// Commitment to the set of transactions
+    set_hash: SetHash,
+}
+
+/// An order-independent commitment to a set of transactions, which is the XOR of their hashes. It
+/// is updated in constant time when a transaction is inserted or removed, and removing a
+/// transaction restores the previous digest, since XOR is its own inverse. It lets two nodes check
+/// cheaply whether their memory pools agree, but it is not collision resistant: one can craft a
+/// set of transactions with any given digest, so it must not be used as a consensus commitment.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SetHash {
+    digest: [u8; 32],
+}
+
+impl SetHash {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Add a transaction to the set.
+    pub fn insert(&mut self, tx: &Transaction) {
+        self.toggle(&tx.hash());
+    }
+
+    /// Remove a transaction from the set. The transaction must be in the set.
+    pub fn remove(&mut self, tx: &Transaction) {
+        self.toggle(&tx.hash());
+    }
+
+    /// Returns the digest of the set, which is all zeros for the empty set.
+    pub fn digest(&self) -> H256 {
+        (&self.digest).into()
+    }
+
+    fn toggle(&mut self, hash: &H256) {
+        for (d, h) in self.digest.iter_mut().zip(hash.as_ref()) {
+            *d ^= h;
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -38,6 +78,7 @@ impl MemoryPool {
             by_hash: HashMap::new(),
             by_input: HashMap::new(),
             by_storage_index: BTreeMap::new(),
+            set_hash: SetHash::new(),
         }
     }
 
@@ -48,6 +89,7 @@ impl MemoryPool {
         }
         // assumes no duplicates nor double spends
         let hash = tx.hash();
+        self.set_hash.insert(&tx);
         let entry = Entry {
             transaction: tx,
             storage_index: self.counter,
@@ -87,6 +129,7 @@ impl MemoryPool {
 
     fn remove_and_get(&mut self, hash: &H256) -> Option<Entry> {
         let entry = self.by_hash.remove(hash)?;
+        self.set_hash.remove(&entry.transaction);
         for input in &entry.transaction.input {
             self.by_input.remove(&input);
         }
@@ -125,12 +168,14 @@ impl MemoryPool {
         }
     }
 
-    /// get n transaction by fifo
-    pub fn get_transactions(&self, n: u32) -> Vec<Transaction> {
+    /// get n transaction by fifo, skipping those whose locktime has not passed at the given time
+    pub fn get_transactions(&self, n: u32, time: u64) -> Vec<Transaction> {
         self.by_storage_index
             .values()
+            .map(|hash| &self.get(hash).unwrap().transaction)
+            .filter(|tx| tx.is_final(time))
             .take(n as usize)
-            .map(|hash| self.get(hash).unwrap().transaction.clone())
+            .cloned()
             .collect()
     }
 
@@ -138,7 +183,143 @@ impl MemoryPool {
     pub fn len(&self) -> usize {
         self.by_hash.len()
     }
+
+    /// Returns the digest of the set of transactions in the memory pool, see `SetHash`.
+    pub fn digest(&self) -> H256 {
+        self.set_hash.digest()
+    }
+}
+
+/// Greedily pick transactions to pack into a block of at most `max_bytes` bytes: in decreasing
+/// order of fee rate, each transaction is taken if it still fits, so a smaller one may fill the
+/// space left by a larger one that does not. Transactions that create value are never taken.
+/// Returns the indices of the chosen transactions, in the order they were taken.
+pub fn select_transactions(candidates: &[Transaction], max_bytes: u32) -> Vec<usize> {
+    let mut by_fee_rate: Vec<(usize, f64)> = candidates
+        .iter()
+        .enumerate()
+        .filter_map(|(index, tx)| tx.fee_rate().map(|rate| (index, rate)))
+        .collect();
+    // a stable sort keeps the earlier of two transactions with the same fee rate first
+    by_fee_rate.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    let mut remaining = max_bytes as usize;
+    let mut selected = vec![];
+    for (index, _) in by_fee_rate {
+        let size = candidates[index].size();
+        if size <= remaining {
+            remaining -= size;
+            selected.push(index);
+        }
+    }
+    selected
 }
 
 #[cfg(test)]
-pub mod tests {}
+pub mod tests {
+    use super::*;
+    use crate::transaction::tests::{generate_keypair, generate_signed_transaction};
+
+    #[test]
+    fn select_transactions() {
+        use crate::crypto::hash::tests::generate_random_hash;
+        use crate::transaction::tests::generate_random_input;
+        use crate::transaction::TransactionBuilder;
+        // a transaction with the given number of inputs of 10 each, paying the given fee
+        let transaction = |inputs: u64, fee: u64| {
+            let mut builder = TransactionBuilder::new();
+            for _ in 0..inputs {
+                builder = builder.add_input(generate_random_input(10));
+            }
+            builder
+                .add_output(inputs * 10 - fee, generate_random_hash())
+                .build()
+        };
+        let candidates = vec![
+            transaction(1, 1),
+            transaction(4, 8),
+            transaction(1, 5),
+            transaction(20, 200),
+            transaction(2, 3),
+        ];
+        let sizes: Vec<usize> = candidates.iter().map(|x| x.size()).collect();
+
+        // the large one does not fit at all, and the budget takes the two best of the rest
+        let budget = sizes[2] + sizes[1];
+        let selected = super::select_transactions(&candidates, budget as u32);
+        assert_eq!(selected, vec![2, 1]);
+
+        // a smaller transaction fills the space left
+        let budget = sizes[2] + sizes[1] + sizes[0];
+        let selected = super::select_transactions(&candidates, budget as u32);
+        assert_eq!(selected, vec![2, 1, 0]);
+
+        for budget in (0..1000).step_by(37) {
+            let selected = super::select_transactions(&candidates, budget);
+            let total: usize = selected.iter().map(|x| sizes[*x]).sum();
+            assert!(total <= budget as usize);
+        }
+        assert!(super::select_transactions(&candidates, 0).is_empty());
+    }
+
+    #[test]
+    fn get_transactions() {
+        let txs: Vec<Transaction> = (0..3)
+            .map(|_| generate_signed_transaction(&[generate_keypair()]))
+            .collect();
+        let mut locked = generate_signed_transaction(&[generate_keypair()]);
+        locked.locktime = 1000;
+        let mut pool = MemoryPool::new(10);
+        pool.insert(locked.clone());
+        for tx in &txs {
+            pool.insert(tx.clone());
+        }
+        // the locked transaction is skipped before its locktime, without taking a slot
+        assert_eq!(
+            pool.get_transactions(2, 999),
+            vec![txs[0].clone(), txs[1].clone()]
+        );
+        assert_eq!(pool.get_transactions(2, 1000), vec![locked, txs[0].clone()]);
+    }
+
+    #[test]
+    fn set_hash() {
+        let txs: Vec<Transaction> = (0..3)
+            .map(|_| generate_signed_transaction(&[generate_keypair()]))
+            .collect();
+        let mut set_hash = SetHash::new();
+        assert!(set_hash.digest().is_zero());
+        for tx in &txs {
+            set_hash.insert(tx);
+        }
+        set_hash.remove(&txs[1]);
+        let mut remaining = SetHash::new();
+        remaining.insert(&txs[2]);
+        remaining.insert(&txs[0]);
+        assert_eq!(set_hash, remaining);
+        set_hash.remove(&txs[0]);
+        set_hash.remove(&txs[2]);
+        assert!(set_hash.digest().is_zero());
+    }
+
+    #[test]
+    fn digest() {
+        let txs: Vec<Transaction> = (0..3)
+            .map(|_| generate_signed_transaction(&[generate_keypair()]))
+            .collect();
+        let mut pool = MemoryPool::new(10);
+        for tx in &txs {
+            pool.insert(tx.clone());
+        }
+        let mut other = MemoryPool::new(10);
+        for tx in txs.iter().rev() {
+            other.insert(tx.clone());
+        }
+        assert_eq!(pool.digest(), other.digest());
+        pool.remove_by_hash(&txs[1].hash());
+        assert_ne!(pool.digest(), other.digest());
+        let mut expected = SetHash::new();
+        expected.insert(&txs[0]);
+        expected.insert(&txs[2]);
+        assert_eq!(pool.digest(), expected.digest());
+    }
+}
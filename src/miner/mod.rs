@@ -22,6 +22,7 @@ use std::time::SystemTime;
 
 use rand::distributions::Distribution;
 use std::collections::BTreeSet;
+use std::convert::TryFrom;
 use std::sync::{Arc, Mutex};
 use std::thread;
 
@@ -263,7 +264,10 @@ impl Context {
             // update transaction block content
             if new_transaction_block {
                 let mempool = self.mempool.lock().unwrap();
-                let transactions = mempool.get_transactions(self.config.tx_txs);
+                // the timestamp of the block only increases from now on, so the transactions final
+                // now are final in the block
+                let time = u64::try_from(get_time()).unwrap_or(u64::MAX);
+                let transactions = mempool.get_transactions(self.config.tx_txs, time);
                 drop(mempool);
                 let _chain_id: usize = TRANSACTION_INDEX as usize;
                 if let Content::Transaction(c) = &mut self.contents[TRANSACTION_INDEX as usize] {
@@ -412,7 +416,7 @@ impl Context {
 
             // Check if we successfully mined a block
             let header_hash = self.header.hash();
-            if header_hash < self.header.difficulty {
+            if header_hash.meets_difficulty(&self.header.difficulty) {
                 // Create a block
                 let mined_block: Block = self.produce_block(header_hash);
                 //if the mined block is an empty tx block, we ignore it, and go straight to next mining loop
@@ -0,0 +1,242 @@
+use super::{Address, Transaction};
+use std::convert::TryFrom;
+
+/// The maximum size of a filter in bytes.
+pub const MAX_FILTER_BYTES: usize = 36_000;
+
+/// The maximum number of bits set for each address.
+pub const MAX_HASH_COUNT: u32 = 50;
+
+/// A Bloom filter over addresses, which a light client sends to a full node to receive the
+/// transactions touching its addresses without revealing them exactly: the filter may also match
+/// other addresses, at a false positive rate the client chooses, but never misses an inserted one.
+///
+/// An address is mapped to `hash_count` bits. Since addresses are SHA256 hashes, the bit indices
+/// are derived from the address bytes directly: the first two 8-byte words `h1` and `h2` give the
+/// indices `h1 + i * h2` modulo the number of bits, for `i` from 0 to `hash_count - 1`.
+///
+/// Since filters come from peers, decoding one fails unless it has between 1 and
+/// `MAX_FILTER_BYTES` bytes and a `hash_count` between 1 and `MAX_HASH_COUNT`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(try_from = "RawAddressFilter")]
+pub struct AddressFilter {
+    /// The bits of the filter.
+    bits: Vec<u8>,
+    /// The number of bits set for each address.
+    hash_count: u32,
+}
+
+impl AddressFilter {
+    /// Create an empty filter of the given number of bits, rounded up to a whole number of bytes,
+    /// setting `hash_count` bits for each address. Both are at least 1, and at most
+    /// `MAX_FILTER_BYTES` bytes and `MAX_HASH_COUNT` respectively.
+    pub fn new(bit_count: usize, hash_count: u32) -> Self {
+        let byte_count = (bit_count.max(1) - 1) / 8 + 1;
+        Self {
+            bits: vec![0; byte_count.min(MAX_FILTER_BYTES)],
+            hash_count: hash_count.clamp(1, MAX_HASH_COUNT),
+        }
+    }
+
+    /// Create an empty filter sized for the given number of addresses to have about the given
+    /// false positive rate, up to `MAX_FILTER_BYTES`. The rate is clamped to the open interval
+    /// between 0 and 1.
+    pub fn with_rate(address_count: usize, false_positive_rate: f64) -> Self {
+        let n = address_count.max(1) as f64;
+        let ln2 = std::f64::consts::LN_2;
+        let rate = if false_positive_rate > 0.0 {
+            false_positive_rate.min(1.0 - f64::EPSILON)
+        } else {
+            f64::MIN_POSITIVE
+        };
+        let bit_count = (-n * rate.ln() / (ln2 * ln2))
+            .ceil()
+            .min((MAX_FILTER_BYTES * 8) as f64);
+        let hash_count = (bit_count / n * ln2).round();
+        Self::new(bit_count as usize, hash_count as u32)
+    }
+
+    /// Add an address to the filter.
+    pub fn insert(&mut self, address: &Address) {
+        for index in self.indices(address) {
+            self.bits[index / 8] |= 1 << (index % 8);
+        }
+    }
+
+    /// Returns whether the address may be in the filter. It is if it has been inserted.
+    pub fn contains(&self, address: &Address) -> bool {
+        self.indices(address)
+            .all(|index| self.bits[index / 8] & (1 << (index % 8)) != 0)
+    }
+
+    /// Returns whether any input owner or output recipient of the transaction may be in the filter.
+    pub fn matches_transaction(&self, transaction: &Transaction) -> bool {
+        transaction.input.iter().any(|x| self.contains(&x.owner))
+            || transaction
+                .output
+                .iter()
+                .any(|x| self.contains(&x.recipient))
+    }
+
+    /// Returns the indices of the bits of an address.
+    fn indices(&self, address: &Address) -> impl Iterator<Item = usize> {
+        let bytes: &[u8] = address.as_ref();
+        let mut word = [0u8; 8];
+        word.copy_from_slice(&bytes[0..8]);
+        let h1 = u64::from_le_bytes(word);
+        word.copy_from_slice(&bytes[8..16]);
+        let h2 = u64::from_le_bytes(word);
+        let bit_count = self.bits.len() as u64 * 8;
+        (0..u64::from(self.hash_count))
+            .map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % bit_count) as usize)
+    }
+}
+
+/// The fields of an `AddressFilter` as decoded, before they are checked.
+#[derive(Deserialize)]
+struct RawAddressFilter {
+    bits: Vec<u8>,
+    hash_count: u32,
+}
+
+impl TryFrom<RawAddressFilter> for AddressFilter {
+    type Error = FilterError;
+
+    fn try_from(raw: RawAddressFilter) -> Result<Self, FilterError> {
+        if raw.bits.is_empty() || raw.bits.len() > MAX_FILTER_BYTES {
+            return Err(FilterError::InvalidSize(raw.bits.len()));
+        }
+        if raw.hash_count == 0 || raw.hash_count > MAX_HASH_COUNT {
+            return Err(FilterError::InvalidHashCount(raw.hash_count));
+        }
+        Ok(Self {
+            bits: raw.bits,
+            hash_count: raw.hash_count,
+        })
+    }
+}
+
+/// An error when decoding an `AddressFilter`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum FilterError {
+    /// The filter has no bytes, or more than `MAX_FILTER_BYTES`.
+    InvalidSize(usize),
+    /// The filter sets no bit, or more than `MAX_HASH_COUNT` bits, for each address.
+    InvalidHashCount(u32),
+}
+
+impl std::fmt::Display for FilterError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            FilterError::InvalidSize(size) => write!(f, "invalid filter size of {} bytes", size),
+            FilterError::InvalidHashCount(count) => {
+                write!(f, "invalid filter hash count of {}", count)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FilterError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::hash::tests::generate_random_hash;
+    use crate::transaction::tests::generate_random_input;
+    use crate::transaction::TransactionBuilder;
+
+    /// Returns the share of random addresses that a filter of the given number of addresses
+    /// wrongly matches.
+    fn false_positive_rate(address_count: usize, target_rate: f64) -> f64 {
+        let mut filter = AddressFilter::with_rate(address_count, target_rate);
+        for _ in 0..address_count {
+            filter.insert(&generate_random_hash());
+        }
+        let trials = 10_000;
+        let matches = (0..trials)
+            .filter(|_| filter.contains(&generate_random_hash()))
+            .count();
+        matches as f64 / f64::from(trials)
+    }
+
+    #[test]
+    fn no_false_negatives() {
+        let addresses: Vec<Address> = (0..200).map(|_| generate_random_hash()).collect();
+        let mut filter = AddressFilter::with_rate(100, 0.01);
+        for address in &addresses {
+            filter.insert(address);
+        }
+        assert!(addresses.iter().all(|x| filter.contains(x)));
+        // even in a filter of one bit
+        let mut tiny = AddressFilter::new(0, 0);
+        tiny.insert(&addresses[0]);
+        assert!(tiny.contains(&addresses[0]));
+    }
+
+    #[test]
+    fn false_positive_rate_is_tunable() {
+        let low = false_positive_rate(500, 0.01);
+        let high = false_positive_rate(500, 0.2);
+        assert!(low < 0.03);
+        assert!(high > 0.1 && high < 0.3);
+        assert!(low < high);
+    }
+
+    #[test]
+    fn bounded_size() {
+        // out of range rates give a filter of bounded size instead of a huge allocation
+        for rate in &[0.0, -1.0, 1.0, 2.0, f64::NAN] {
+            let filter = AddressFilter::with_rate(1000, *rate);
+            assert!(!filter.bits.is_empty() && filter.bits.len() <= MAX_FILTER_BYTES);
+            assert!(filter.hash_count >= 1 && filter.hash_count <= MAX_HASH_COUNT);
+        }
+        let filter = AddressFilter::with_rate(usize::MAX, 0.01);
+        assert_eq!(filter.bits.len(), MAX_FILTER_BYTES);
+        let filter = AddressFilter::new(usize::MAX, u32::MAX);
+        assert_eq!(filter.bits.len(), MAX_FILTER_BYTES);
+        assert_eq!(filter.hash_count, MAX_HASH_COUNT);
+    }
+
+    #[test]
+    fn malformed() {
+        let decode = |json: &str| serde_json::from_str::<AddressFilter>(json);
+        assert!(decode(r#"{"bits":[],"hash_count":3}"#).is_err());
+        assert!(decode(r#"{"bits":[1],"hash_count":0}"#).is_err());
+        assert!(decode(r#"{"bits":[1],"hash_count":4294967295}"#).is_err());
+        let oversized = AddressFilter {
+            bits: vec![0; MAX_FILTER_BYTES + 1],
+            hash_count: 1,
+        };
+        assert!(decode(&serde_json::to_string(&oversized).unwrap()).is_err());
+
+        let mut filter = AddressFilter::with_rate(10, 0.01);
+        filter.insert(&generate_random_hash());
+        let encoded = bincode::serialize(&filter).unwrap();
+        assert_eq!(
+            bincode::deserialize::<AddressFilter>(&encoded).unwrap(),
+            filter
+        );
+        let mut empty = encoded.clone();
+        empty.splice(0..8 + filter.bits.len(), 0u64.to_le_bytes().iter().cloned());
+        assert!(bincode::deserialize::<AddressFilter>(&empty).is_err());
+    }
+
+    #[test]
+    fn matches_transaction() {
+        let owner = generate_random_hash();
+        let recipient = generate_random_hash();
+        let mut input = generate_random_input(10);
+        input.owner = owner;
+        let transaction = TransactionBuilder::new()
+            .add_input(input)
+            .add_output(10, recipient)
+            .build();
+        let mut filter = AddressFilter::with_rate(10, 0.0001);
+        assert!(!filter.matches_transaction(&transaction));
+        filter.insert(&owner);
+        assert!(filter.matches_transaction(&transaction));
+        let mut filter = AddressFilter::with_rate(10, 0.0001);
+        filter.insert(&recipient);
+        assert!(filter.matches_transaction(&transaction));
+    }
+}
@@ -30,7 +30,10 @@ pub enum BlockResult {
     WrongVoteLevel,
     EmptyTransaction,
     ZeroValue,
+    DuplicateInput,
     InsufficientInput,
+    /// A transaction is locked until after the timestamp of the block.
+    LockedTransaction,
     WrongSignature,
 }
 
@@ -51,7 +54,9 @@ impl std::fmt::Display for BlockResult {
             BlockResult::ZeroValue => {
                 write!(f, "transaction input or output value contains a zero")
             }
+            BlockResult::DuplicateInput => write!(f, "transaction spends a coin twice"),
             BlockResult::InsufficientInput => write!(f, "insufficient input"),
+            BlockResult::LockedTransaction => write!(f, "transaction locktime has not passed"),
             BlockResult::WrongSignature => write!(f, "signature mismatch"),
         }
     }
@@ -173,9 +178,15 @@ pub fn check_content_semantic(
                 if !transaction::check_non_zero(&transaction) {
                     return BlockResult::ZeroValue;
                 }
+                if !transaction::check_no_duplicate_inputs(&transaction) {
+                    return BlockResult::DuplicateInput;
+                }
                 if !transaction::check_sufficient_input(&transaction) {
                     return BlockResult::InsufficientInput;
                 }
+                if !transaction::check_locktime(&transaction, block.header.timestamp) {
+                    return BlockResult::LockedTransaction;
+                }
             }
             if !transaction::check_signature_batch(&content.transactions) {
                 return BlockResult::WrongSignature;
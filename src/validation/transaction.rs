@@ -1,7 +1,6 @@
-use crate::transaction::Transaction;
-
-use ed25519_dalek::PublicKey;
-use ed25519_dalek::Signature;
+use crate::config::NETWORK_ID;
+use crate::transaction::{verify_batch, Transaction};
+use std::convert::TryFrom;
 
 /// Checks that input and output are non-empty
 pub fn check_non_empty(transaction: &Transaction) -> bool {
@@ -14,37 +13,23 @@ pub fn check_non_zero(transaction: &Transaction) -> bool {
         || transaction.output.iter().any(|x| x.value == 0))
 }
 
-/// Checks if input_sum >= output_sum
-pub fn check_sufficient_input(transaction: &Transaction) -> bool {
-    let input_sum: u64 = transaction.input.iter().map(|x| x.value).sum();
-    let output_sum: u64 = transaction.output.iter().map(|x| x.value).sum();
-    input_sum >= output_sum
+/// Checks that no coin is spent twice by the transaction
+pub fn check_no_duplicate_inputs(transaction: &Transaction) -> bool {
+    !transaction.has_duplicate_inputs()
 }
 
-pub fn check_signature_batch(transactions: &[Transaction]) -> bool {
-    let mut raw_messages: Vec<Vec<u8>> = vec![];
-    let mut messages: Vec<&[u8]> = vec![];
-    let mut signatures: Vec<Signature> = vec![];
-    let mut public_keys: Vec<PublicKey> = vec![];
-
-    for (_idx, tx) in transactions.iter().enumerate() {
-        let raw_inputs = bincode::serialize(&tx.input).unwrap();
-        let raw_outputs = bincode::serialize(&tx.output).unwrap();
-        let raw = [&raw_inputs[..], &raw_outputs[..]].concat();
-        raw_messages.push(raw);
-    }
+/// Checks if input_sum >= output_sum, and that neither sum overflows
+pub fn check_sufficient_input(transaction: &Transaction) -> bool {
+    transaction.validate_value().is_ok()
+}
 
-    for (idx, tx) in transactions.iter().enumerate() {
-        for a in &tx.authorization {
-            public_keys.push(PublicKey::from_bytes(&a.pubkey).unwrap());
-            signatures.push(Signature::from_bytes(&a.signature).unwrap());
-            messages.push(&raw_messages[idx]);
-        }
-    }
+/// Checks that the locktime of the transaction has passed at the given block timestamp
+pub fn check_locktime(transaction: &Transaction, timestamp: u128) -> bool {
+    transaction.is_final(u64::try_from(timestamp).unwrap_or(u64::MAX))
+}
 
+/// Checks the authorizations of all transactions
+pub fn check_signature_batch(transactions: &[Transaction]) -> bool {
     // TODO: tune the batch size
-    match ed25519_dalek::verify_batch(&messages, &signatures, &public_keys) {
-        Ok(()) => true,
-        Err(_) => false,
-    }
+    verify_batch(transactions, NETWORK_ID).iter().all(|x| *x)
 }
@@ -1,9 +1,11 @@
-use crate::transaction::{Address, Authorization, CoinId, Input, Output, Transaction};
+use crate::config::NETWORK_ID;
+use crate::transaction::{
+    address_from_pubkey, Address, Authorization, CoinId, Input, Output, Transaction,
+};
 use bincode::serialize;
 use ed25519_dalek::Keypair;
 use rand::rngs::OsRng;
 
-use std::cell::RefCell;
 use std::collections::HashMap;
 
 use std::sync::atomic::{AtomicUsize, Ordering};
@@ -91,8 +93,7 @@ impl Wallet {
 
     pub fn load_keypair(&self, keypair: Keypair) -> Result<Address> {
         let cf = self.db.cf_handle(KEYPAIR_CF).unwrap();
-        let addr: Address =
-            ring::digest::digest(&ring::digest::SHA256, &keypair.public.as_bytes().as_ref()).into();
+        let addr = address_from_pubkey(&keypair.public);
         self.db.put_cf(cf, &addr, &keypair.to_bytes().to_vec())?;
         let mut keypairs = self.keypairs.lock().unwrap();
         keypairs.insert(addr, keypair);
@@ -203,18 +204,11 @@ impl Wallet {
         }
 
         let mut owners: Vec<Address> = inputs.iter().map(|input| input.owner).collect();
-        let unsigned = Transaction {
-            input: inputs,
-            output,
-            authorization: vec![],
-            hash: RefCell::new(None),
-        };
+        let unsigned = Transaction::new(inputs, output);
         let mut authorization = vec![];
         owners.sort_unstable();
         owners.dedup();
-        let raw_inputs = bincode::serialize(&unsigned.input).unwrap();
-        let raw_outputs = bincode::serialize(&unsigned.output).unwrap();
-        let raw_unsigned = [&raw_inputs[..], &raw_outputs[..]].concat();
+        let raw_unsigned = unsigned.signing_bytes(NETWORK_ID);
         for owner in owners.iter() {
             let keypairs = self.keypairs.lock().unwrap();
             if let Some(v) = keypairs.get(&owner) {